@@ -0,0 +1,181 @@
+//! Bitboard match checking against matching on the hash map directly, and the valid move
+//! search built on it.
+//!
+//! ```text
+//! cargo bench --bench matching
+//! ```
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use hashbrown::HashMap;
+use matchup::bitboard::Bitboard;
+use matchup::board::Board;
+use matchup::core::Position;
+use matchup::generator;
+use matchup::moves;
+use matchup::piece::{Piece, TokenType};
+use matchup::rng::GameRng;
+use matchup::shape::BoardShape;
+use matchup::token_grid;
+use rand::seq::SliceRandom;
+
+const SIZES: [i32; 2] = [10, 20];
+
+/// Regular colours only, as the generator fills boards, but with matches left in
+fn random_pieces(size: i32) -> HashMap<Position, Piece> {
+    let mut rng = GameRng::from_seed(size as u64);
+    let mut pieces = HashMap::new();
+    for y in 0..size {
+        for x in 0..size {
+            let colour = *TokenType::ALL_REGULAR.choose(&mut rng).unwrap();
+            pieces.insert(Position::new(x, y), Piece::new(colour));
+        }
+    }
+    pieces
+}
+
+/// A playable board straight from the generator, as the game and the bots start on
+fn generated_board(size: i32) -> Board {
+    let template = Board::new(HashMap::new(), BoardShape::square(size as usize));
+    generator::generate_board(&template, &mut GameRng::from_seed(size as u64)).unwrap()
+}
+
+/// Every pair of neighbouring cells, as the valid move search tries them
+fn adjacent_pairs(size: i32) -> Vec<(Position, Position)> {
+    let mut pairs = Vec::new();
+    for y in 0..size {
+        for x in 0..size {
+            if x + 1 < size {
+                pairs.push((Position::new(x, y), Position::new(x + 1, y)));
+            }
+            if y + 1 < size {
+                pairs.push((Position::new(x, y), Position::new(x, y + 1)));
+            }
+        }
+    }
+    pairs
+}
+
+fn entire_grid(c: &mut Criterion) {
+    let mut group = c.benchmark_group("entire_grid");
+    for size in SIZES {
+        let pieces = random_pieces(size);
+        group.bench_with_input(BenchmarkId::new("hash_map", size), &pieces, |b, pieces| {
+            b.iter(|| token_grid::hash_map_check_entire_grid(black_box(pieces)))
+        });
+        group.bench_with_input(BenchmarkId::new("bitboard", size), &pieces, |b, pieces| {
+            b.iter(|| Bitboard::from_pieces(black_box(pieces)).map(|bits| bits.check_entire_grid()))
+        });
+    }
+    group.finish();
+}
+
+fn swap_checks(c: &mut Criterion) {
+    let mut group = c.benchmark_group("swap_checks");
+    for size in SIZES {
+        let pieces = random_pieces(size);
+        let pairs = adjacent_pairs(size);
+        group.bench_with_input(BenchmarkId::new("hash_map", size), &pieces, |b, pieces| {
+            b.iter(|| {
+                pairs
+                    .iter()
+                    .filter(|(from, to)| {
+                        token_grid::hash_map_swap_makes_match(black_box(pieces), from, to)
+                    })
+                    .count()
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("bitboard", size), &pieces, |b, pieces| {
+            b.iter(|| {
+                let mut bits = Bitboard::from_pieces(black_box(pieces)).unwrap();
+                pairs
+                    .iter()
+                    .filter(|(from, to)| bits.swap_makes_match(from, to))
+                    .count()
+            })
+        });
+    }
+    group.finish();
+}
+
+/// The player's swaps: checked one at a time as they're made, then the matches found once made
+fn post_swap(c: &mut Criterion) {
+    let mut group = c.benchmark_group("post_swap");
+    for size in SIZES {
+        let board = generated_board(size);
+        let pairs = adjacent_pairs(size);
+        group.bench_with_input(
+            BenchmarkId::new("is_valid_swap", size),
+            &board,
+            |b, board| {
+                b.iter(|| {
+                    pairs
+                        .iter()
+                        .filter(|(from, to)| black_box(board).is_valid_swap(from, to))
+                        .count()
+                })
+            },
+        );
+
+        // Each valid swap already made, with the two cells it moved
+        let swapped = moves::valid_swaps(&board)
+            .into_iter()
+            .map(|(from, to)| {
+                let mut after = board.clone();
+                after.swap(&from, &to);
+                (after.pieces, [from, to])
+            })
+            .collect::<Vec<_>>();
+        group.bench_with_input(
+            BenchmarkId::new("hash_map", size),
+            &swapped,
+            |b, swapped| {
+                b.iter(|| {
+                    swapped
+                        .iter()
+                        .map(|(pieces, dirty)| {
+                            token_grid::hash_map_check_for_matches(black_box(pieces), dirty).len()
+                        })
+                        .sum::<usize>()
+                })
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("bitboard", size),
+            &swapped,
+            |b, swapped| {
+                b.iter(|| {
+                    swapped
+                        .iter()
+                        .map(|(pieces, dirty)| {
+                            token_grid::check_for_matches(black_box(pieces), dirty).len()
+                        })
+                        .sum::<usize>()
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+fn valid_moves(c: &mut Criterion) {
+    let mut group = c.benchmark_group("valid_moves");
+    for size in SIZES {
+        let board = generated_board(size);
+        group.bench_with_input(
+            BenchmarkId::new("find_valid_moves", size),
+            &board,
+            |b, board| b.iter(|| moves::find_valid_moves(black_box(board))),
+        );
+        group.bench_with_input(BenchmarkId::new("valid_swaps", size), &board, |b, board| {
+            b.iter(|| moves::valid_swaps(black_box(board)))
+        });
+        group.bench_with_input(
+            BenchmarkId::new("has_valid_move", size),
+            &board,
+            |b, board| b.iter(|| moves::has_valid_move(black_box(board))),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, entire_grid, swap_checks, post_swap, valid_moves);
+criterion_main!(benches);
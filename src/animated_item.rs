@@ -1,6 +1,8 @@
-use crate::core::{Position, TextureAtlas};
 use macroquad::color::WHITE;
+use macroquad::math::Rect;
 use macroquad::prelude::draw_texture;
+use macroquad::texture::{load_image, Texture2D};
+use matchup::core::Position;
 
 #[derive(Clone, Debug)]
 pub struct AnimatedItem {
@@ -42,7 +44,7 @@ impl AnimatedItem {
         }
     }
 
-    pub fn update(&mut self) {
+    pub fn update(&mut self, now: f64) {
         if let PlayingState::Playing(start_time) = self.playing {
             let time_to_next_tick = match self.animation_scheme {
                 AnimationScheme::TimePerFrame(time_per_frame) => time_per_frame,
@@ -50,46 +52,48 @@ impl AnimatedItem {
                     total_time / self.texture_atlas.size() as f64
                 }
             };
-            if macroquad::time::get_time() - start_time > time_to_next_tick {
-                self.playing = PlayingState::Playing(macroquad::time::get_time());
+            if now - start_time > time_to_next_tick {
+                self.playing = PlayingState::Playing(now);
                 self.incr();
             }
         }
     }
 
-    pub fn start(&mut self) {
+    pub fn start(&mut self, now: f64) {
         println!("Starting {:?}", self);
-        self.playing = PlayingState::Playing(macroquad::time::get_time());
+        self.playing = PlayingState::Playing(now);
     }
+    // Effects run on the game clock so already freeze with it, these are for driving an item by hand
+    #[allow(dead_code)]
     pub fn stop(&mut self) {
         self.playing = PlayingState::Stopped;
     }
-    pub fn pause(&mut self) {
+    #[allow(dead_code)]
+    pub fn pause(&mut self, now: f64) {
         if let PlayingState::Playing(start_time) = self.playing {
-            self.playing = PlayingState::Paused(start_time);
+            self.playing = PlayingState::Paused(now - start_time);
         }
     }
-    pub fn resume(&mut self) {
+    #[allow(dead_code)]
+    pub fn resume(&mut self, now: f64) {
         match self.playing {
-            PlayingState::Paused(start_time) => {
-                self.playing = PlayingState::Playing(start_time);
+            PlayingState::Paused(elapsed) => {
+                self.playing = PlayingState::Playing(now - elapsed);
             }
             PlayingState::Stopped => {
-                self.playing = PlayingState::Playing(macroquad::time::get_time());
+                self.playing = PlayingState::Playing(now);
             }
             _ => {}
         }
     }
     pub fn is_playing(&self) -> bool {
-        match self.playing {
-            PlayingState::Playing(_) => true,
-            _ => false,
-        }
+        matches!(self.playing, PlayingState::Playing(_))
     }
 }
 
 #[derive(Clone, Debug)]
 pub enum AnimationScheme {
+    #[allow(dead_code)]
     TimePerFrame(f64),
     TotalTime(f64),
 }
@@ -97,6 +101,45 @@ pub enum AnimationScheme {
 #[derive(Clone, Debug)]
 enum PlayingState {
     Playing(f64),
+    // How far into the current frame it was paused
+    #[allow(dead_code)]
     Paused(f64),
     Stopped,
 }
+
+#[derive(Clone, Debug)]
+pub struct TextureAtlas {
+    textures: Vec<Texture2D>,
+    #[allow(dead_code)]
+    pub texture_size: (f32, f32),
+}
+
+impl TextureAtlas {
+    pub async fn new(filename: &str, texture_size: (f32, f32), rows: i32, cols: i32) -> Self {
+        let rect = Rect::new(0f32, 0f32, texture_size.0, texture_size.1);
+        let image = load_image(filename).await.unwrap();
+        let mut textures = Vec::new();
+        for col in 0..cols {
+            for row in 0..rows {
+                let rect = Rect::new(
+                    rect.x + (col as f32 * rect.w),
+                    rect.y + (row as f32 * rect.h),
+                    rect.w,
+                    rect.h,
+                );
+                let texture = Texture2D::from_image(&image.sub_image(rect));
+                textures.push(texture);
+            }
+        }
+        Self {
+            textures,
+            texture_size,
+        }
+    }
+    pub fn get(&self, index: usize) -> Texture2D {
+        self.textures[index]
+    }
+    pub fn size(&self) -> usize {
+        self.textures.len()
+    }
+}
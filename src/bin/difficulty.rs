@@ -0,0 +1,207 @@
+//! Estimates how hard levels are by having a bot play each of them many times headless.
+//!
+//! ```text
+//! cargo run --release --bin difficulty -- res/levels/04_frozen.ron heart --games 2000 --csv
+//! ```
+//!
+//! Levels are given as files or by name. `--bot` picks the strategy (greedy by default, see
+//! [`Strategy::from_name`]), `--mode` the game mode, `--seed` the first seed of the run,
+//! `--max-moves` where endless games are cut off and `--csv` prints CSV rather than a table.
+use matchup::level::{self, Level};
+use matchup::mode::GameMode;
+use matchup::solver::{self, Bot, Strategy, Summary, DEFAULT_MAX_MOVES};
+use std::path::Path;
+
+const DEFAULT_GAMES: u32 = 1000;
+// Cascades this long or longer share a column
+const CASCADE_COLUMNS: usize = 5;
+// Flags that are followed by a value, so the value isn't taken for a level
+const VALUE_FLAGS: [&str; 5] = ["--games", "--bot", "--mode", "--seed", "--max-moves"];
+
+fn arg_value<T: std::str::FromStr>(args: &[String], name: &str) -> Option<T> {
+    let idx = args.iter().position(|arg| arg == name)?;
+    args.get(idx + 1)?.parse().ok()
+}
+
+/// Every argument that isn't a flag or a flag's value
+fn level_args(args: &[String]) -> Vec<&String> {
+    let mut levels = Vec::new();
+    let mut skip_next = false;
+    for arg in args {
+        if skip_next {
+            skip_next = false;
+        } else if VALUE_FLAGS.contains(&arg.as_str()) {
+            skip_next = true;
+        } else if !arg.starts_with("--") {
+            levels.push(arg);
+        }
+    }
+    levels
+}
+
+/// Load a level from a file, or else find it in the levels directory by name
+fn find_level(wanted: &str) -> Result<Level, String> {
+    let path = Path::new(wanted);
+    if path.is_file() {
+        return Level::load(path).map_err(|err| format!("{}: {}", wanted, err));
+    }
+    level::load_levels(Path::new(level::LEVELS_DIR))
+        .into_iter()
+        .find(|(path, level)| {
+            path.file_stem().is_some_and(|stem| stem == wanted)
+                || level
+                    .as_ref()
+                    .is_ok_and(|level| level.name.eq_ignore_ascii_case(wanted))
+        })
+        .map(|(path, level)| level.map_err(|err| format!("{}: {}", path.display(), err)))
+        .unwrap_or_else(|| Err(format!("no level called {}", wanted)))
+}
+
+/// Play `games` games of `level` spread over every core, seeds counting up from `first_seed`
+fn estimate(
+    level: &Level,
+    mode: GameMode,
+    strategy: Strategy,
+    games: u32,
+    first_seed: u64,
+    max_moves: u32,
+) -> Summary {
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get() as u64);
+    let summaries = std::thread::scope(|scope| {
+        let handles = (0..threads)
+            .map(|thread| {
+                scope.spawn(move || {
+                    let mut summary = Summary::default();
+                    let seeds = (first_seed..first_seed + games as u64)
+                        .skip(thread as usize)
+                        .step_by(threads as usize);
+                    for seed in seeds {
+                        let mut bot = Bot::new(strategy, seed);
+                        match solver::play_game(level, mode, &mut bot, seed, max_moves) {
+                            Some(report) => summary.add(&report),
+                            None => summary.skip(),
+                        }
+                    }
+                    summary
+                })
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect::<Vec<_>>()
+    });
+    let mut total = Summary::default();
+    for summary in summaries.iter() {
+        total.merge(summary);
+    }
+    total
+}
+
+fn cascade_headings() -> Vec<String> {
+    (1..=CASCADE_COLUMNS)
+        .map(|length| match length {
+            CASCADE_COLUMNS => format!("cascade_{}+", length),
+            _ => format!("cascade_{}", length),
+        })
+        .collect()
+}
+
+fn cascade_shares(summary: &Summary) -> Vec<f64> {
+    (1..=CASCADE_COLUMNS)
+        .map(|length| summary.cascade_share(length, length == CASCADE_COLUMNS))
+        .collect()
+}
+
+fn main() {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let games = arg_value(&args, "--games").unwrap_or(DEFAULT_GAMES);
+    let first_seed = arg_value(&args, "--seed").unwrap_or(0);
+    let max_moves = arg_value(&args, "--max-moves").unwrap_or(DEFAULT_MAX_MOVES);
+    let csv = args.iter().any(|arg| arg == "--csv");
+    let strategy = match arg_value::<String>(&args, "--bot") {
+        Some(name) => Strategy::from_name(&name).unwrap_or_else(|| {
+            eprintln!(
+                "Unknown bot {}, expected greedy, lookahead[:depth] or montecarlo[:playouts]",
+                name
+            );
+            std::process::exit(2);
+        }),
+        None => Strategy::Greedy,
+    };
+    let wanted = level_args(&args);
+    if wanted.is_empty() {
+        eprintln!("Usage: difficulty <level file or name>... [--games n] [--bot strategy] [--mode name] [--seed n] [--max-moves n] [--csv]");
+        std::process::exit(2);
+    }
+
+    let mut headings = vec![
+        "level".to_owned(),
+        "mode".to_owned(),
+        "games".to_owned(),
+        "skipped".to_owned(),
+        "win_rate".to_owned(),
+        "avg_moves".to_owned(),
+        "avg_score".to_owned(),
+    ];
+    headings.extend(cascade_headings());
+    if csv {
+        println!("{}", headings.join(","));
+    } else {
+        eprintln!("{:?} playing {} games per level", strategy, games);
+        println!(
+            "{:<20} {:<16} {}",
+            headings[0],
+            headings[1],
+            headings[2..]
+                .iter()
+                .map(|heading| format!("{:>11}", heading))
+                .collect::<String>()
+        );
+    }
+
+    for wanted in wanted {
+        let level = match find_level(wanted) {
+            Ok(level) => level,
+            Err(err) => {
+                eprintln!("Skipping {}", err);
+                continue;
+            }
+        };
+        let mode = match arg_value::<String>(&args, "--mode") {
+            Some(name) => GameMode::from_name(&name, &level).unwrap_or_else(|| {
+                eprintln!("Unknown mode {}, using the level's own", name);
+                GameMode::for_level(&level)
+            }),
+            None => GameMode::for_level(&level),
+        };
+        let summary = estimate(&level, mode, strategy, games, first_seed, max_moves);
+
+        let mut values = vec![
+            summary.games.to_string(),
+            summary.skipped.to_string(),
+            format!("{:.3}", summary.win_rate()),
+            format!("{:.1}", summary.average_moves()),
+            format!("{:.0}", summary.average_score()),
+        ];
+        values.extend(
+            cascade_shares(&summary)
+                .iter()
+                .map(|share| format!("{:.3}", share)),
+        );
+        let mode = format!("{:?}", mode);
+        if csv {
+            println!("\"{}\",\"{}\",{}", level.name, mode, values.join(","));
+        } else {
+            println!(
+                "{:<20} {:<16} {}",
+                level.name,
+                mode,
+                values
+                    .iter()
+                    .map(|value| format!("{:>11}", value))
+                    .collect::<String>()
+            );
+        }
+    }
+}
@@ -0,0 +1,409 @@
+//! Dense per-colour bitboards for match checking. Each colour's cells are packed one `u64` per
+//! row, so runs of three are found with a few shifts and ands, and flood fills grow a whole row
+//! at a time. Matches are the same ones [`crate::token_grid::hash_map_check_entire_grid`] and
+//! [`crate::token_grid::hash_map_check_for_matches`] find, which it is checked against.
+use crate::core::{MatchKind, Position};
+use crate::piece::{Piece, TokenType};
+use crate::token_grid::merge_intersecting_lines;
+use hashbrown::HashMap;
+
+/// Widest spread of columns that fits, one bit per cell in a row
+pub const MAX_WIDTH: i32 = 64;
+const COLOURS: usize = TokenType::ALL_REGULAR.len();
+
+#[derive(Debug, Clone)]
+pub struct Bitboard {
+    // The position bit 0 of row 0 stands for
+    origin: Position,
+    width: i32,
+    height: usize,
+    // Every colour's rows one after another, in `TokenType::ALL_REGULAR` order, so the whole
+    // board takes a single allocation
+    rows: Vec<u64>,
+}
+
+fn colour_index(type_: TokenType) -> Option<usize> {
+    TokenType::ALL_REGULAR
+        .iter()
+        .position(|&colour| colour == type_)
+}
+
+/// Calls `f` with the start and length of each run of three or more set bits, lowest first
+fn for_each_run(mut mask: u64, mut f: impl FnMut(u32, u32)) {
+    while mask != 0 {
+        let start = mask.trailing_zeros();
+        let len = (!(mask >> start)).trailing_zeros();
+        if len >= 3 {
+            f(start, len);
+        }
+        let end = start + len;
+        mask &= if end >= u64::BITS { 0 } else { !0 << end };
+    }
+}
+
+/// Whether three or more cells of `area` are in a row, across or down
+fn has_run(area: &[u64]) -> bool {
+    area.iter().any(|row| row & (row >> 1) & (row >> 2) != 0)
+        || area.windows(3).any(|rows| rows[0] & rows[1] & rows[2] != 0)
+}
+
+impl Bitboard {
+    /// Pack the matchable pieces, None if the pieces span more than [`MAX_WIDTH`] columns
+    pub fn from_pieces(pieces: &HashMap<Position, Piece>) -> Option<Self> {
+        let mut min = Position::new(i32::MAX, i32::MAX);
+        let mut max = Position::new(i32::MIN, i32::MIN);
+        for pos in pieces.keys() {
+            min = Position::new(min.x.min(pos.x), min.y.min(pos.y));
+            max = Position::new(max.x.max(pos.x), max.y.max(pos.y));
+        }
+        if pieces.is_empty() {
+            min = Position::new(0, 0);
+            max = Position::new(-1, -1);
+        }
+        let width = max.x - min.x + 1;
+        if width > MAX_WIDTH {
+            return None;
+        }
+        let height = (max.y - min.y + 1) as usize;
+        let mut rows = vec![0; COLOURS * height];
+        for (pos, piece) in pieces.iter() {
+            if let Some(colour) = colour_index(piece.type_) {
+                rows[colour * height + (pos.y - min.y) as usize] |= 1 << (pos.x - min.x);
+            }
+        }
+        Some(Self {
+            origin: min,
+            width,
+            height,
+            rows,
+        })
+    }
+
+    /// Pack just the cells a swap of the neighbouring `pos_a` and `pos_b` could make a match
+    /// through, those within two of either of them. Costs the same however big the board is, for
+    /// checking a single swap.
+    pub fn around_swap(
+        pieces: &HashMap<Position, Piece>,
+        pos_a: &Position,
+        pos_b: &Position,
+    ) -> Self {
+        let origin = Position::new(pos_a.x.min(pos_b.x) - 2, pos_a.y.min(pos_b.y) - 2);
+        let width = (pos_a.x - pos_b.x).abs() + 5;
+        let height = pos_a.y.abs_diff(pos_b.y) as usize + 5;
+        let mut rows = vec![0; COLOURS * height];
+        for y in 0..height {
+            for x in 0..width {
+                let pos = Position::new(origin.x + x, origin.y + y as i32);
+                let colour = pieces
+                    .get(&pos)
+                    .filter(|piece| piece.is_matchable())
+                    .and_then(|piece| colour_index(piece.type_));
+                if let Some(colour) = colour {
+                    rows[colour * height + y] |= 1 << x;
+                }
+            }
+        }
+        Self {
+            origin,
+            width,
+            height,
+            rows,
+        }
+    }
+
+    fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The rows of one colour, top to bottom
+    fn colour_rows(&self, colour: usize) -> &[u64] {
+        &self.rows[colour * self.height..(colour + 1) * self.height]
+    }
+
+    /// Row and bit standing for `pos`, None if it's off the bitboard
+    fn locate(&self, pos: &Position) -> Option<(usize, u64)> {
+        let (x, y) = (pos.x - self.origin.x, pos.y - self.origin.y);
+        ((0..self.width).contains(&x) && (0..self.height() as i32).contains(&y))
+            .then(|| (y as usize, 1 << x))
+    }
+
+    fn colour_at(&self, y: usize, bit: u64) -> Option<usize> {
+        (0..COLOURS).find(|&colour| self.colour_rows(colour)[y] & bit != 0)
+    }
+
+    /// Swap the contents of two cells, which must both be on the bitboard
+    pub fn swap(&mut self, pos_a: &Position, pos_b: &Position) {
+        let (Some((y_a, bit_a)), Some((y_b, bit_b))) = (self.locate(pos_a), self.locate(pos_b))
+        else {
+            return;
+        };
+        for rows in self.rows.chunks_mut(self.height) {
+            if (rows[y_a] & bit_a != 0) != (rows[y_b] & bit_b != 0) {
+                rows[y_a] ^= bit_a;
+                rows[y_b] ^= bit_b;
+            }
+        }
+    }
+
+    /// Every cell of `colour` connected to the cell at `y` and `bit`
+    fn flood(&self, colour: usize, y: usize, bit: u64) -> Vec<u64> {
+        let cells = self.colour_rows(colour);
+        let mut area = vec![0; cells.len()];
+        area[y] = bit;
+        loop {
+            let mut changed = false;
+            for y in 0..area.len() {
+                let mut grown = area[y] | (area[y] << 1) | (area[y] >> 1);
+                if y > 0 {
+                    grown |= area[y - 1];
+                }
+                if y + 1 < area.len() {
+                    grown |= area[y + 1];
+                }
+                grown &= cells[y];
+                if grown != area[y] {
+                    area[y] = grown;
+                    changed = true;
+                }
+            }
+            if !changed {
+                return area;
+            }
+        }
+    }
+
+    /// Lines of three or more in `area`: across the rows from the top, then down the columns
+    /// from the left
+    fn lines(&self, area: &[u64]) -> Vec<Vec<Position>> {
+        let mut lines = Vec::new();
+        for (y, row) in area.iter().enumerate() {
+            for_each_run(*row, |start, len| {
+                lines.push(
+                    (start..start + len)
+                        .map(|x| self.position(x as i32, y))
+                        .collect(),
+                );
+            });
+        }
+        let mut columns = area
+            .windows(3)
+            .fold(0, |columns, rows| columns | (rows[0] & rows[1] & rows[2]));
+        while columns != 0 {
+            let x = columns.trailing_zeros();
+            columns &= columns - 1;
+            let mut line = Vec::new();
+            for (y, row) in area.iter().enumerate() {
+                if row & (1 << x) != 0 {
+                    line.push(self.position(x as i32, y));
+                } else {
+                    if line.len() >= 3 {
+                        lines.push(line.clone());
+                    }
+                    line.clear();
+                }
+            }
+            if line.len() >= 3 {
+                lines.push(line);
+            }
+        }
+        lines
+    }
+
+    fn position(&self, x: i32, y: usize) -> Position {
+        Position::new(self.origin.x + x, self.origin.y + y as i32)
+    }
+
+    /// Same as [`crate::token_grid::check_entire_grid`], with groups in row order of their
+    /// areas' first cells
+    pub fn check_entire_grid(&self) -> Vec<(Vec<Position>, MatchKind)> {
+        let mut matched_groups = Vec::new();
+        let mut visited = vec![0; self.height()];
+        for y in 0..self.height() {
+            loop {
+                let unvisited = (0..COLOURS)
+                    .fold(0, |cells, colour| cells | self.colour_rows(colour)[y])
+                    & !visited[y];
+                if unvisited == 0 {
+                    break;
+                }
+                let bit = unvisited & unvisited.wrapping_neg();
+                let colour = self.colour_at(y, bit).unwrap();
+                let area = self.flood(colour, y, bit);
+                for (visited, area) in visited.iter_mut().zip(area.iter()) {
+                    *visited |= area;
+                }
+                if has_run(&area) {
+                    matched_groups.extend(merge_intersecting_lines(&self.lines(&area)));
+                }
+            }
+        }
+        matched_groups
+    }
+
+    /// First column and length of the unbroken run of `colour` across the row through the cell at
+    /// `y` and `bit`
+    fn run_across(&self, colour: usize, y: usize, bit: u64) -> (u32, u32) {
+        let row = self.colour_rows(colour)[y];
+        let x = bit.trailing_zeros();
+        // Cells from x rightwards, and from x leftwards, both counting x itself
+        let right = (!(row >> x)).trailing_zeros();
+        let left = (!(row << (u64::BITS - 1 - x))).leading_zeros();
+        (x + 1 - left, left + right - 1)
+    }
+
+    /// First row and length of the unbroken run of `colour` down the column through the cell at
+    /// `y` and `bit`
+    fn run_down(&self, colour: usize, y: usize, bit: u64) -> (usize, usize) {
+        let cells = self.colour_rows(colour);
+        let above = cells[..y]
+            .iter()
+            .rev()
+            .take_while(|row| *row & bit != 0)
+            .count();
+        let below = cells[y + 1..]
+            .iter()
+            .take_while(|row| *row & bit != 0)
+            .count();
+        (y - above, above + below + 1)
+    }
+
+    /// Whether a run of three or more goes through the cell at `y` and `bit`, across or down
+    fn has_run_through(&self, y: usize, bit: u64) -> bool {
+        let Some(colour) = self.colour_at(y, bit) else {
+            return false;
+        };
+        self.run_across(colour, y, bit).1 >= 3 || self.run_down(colour, y, bit).1 >= 3
+    }
+
+    /// Same as [`crate::token_grid::check_for_matches`]: every run of three or more through one
+    /// of `dirty_positions`, grouped with the runs crossing it
+    pub fn check_for_matches(
+        &self,
+        dirty_positions: &[Position],
+    ) -> Vec<(Vec<Position>, MatchKind)> {
+        let mut lines: Vec<Vec<Position>> = Vec::new();
+        // Every cell of a run that's found is looked at in turn too, for runs crossing it
+        let mut unchecked = dirty_positions.to_vec();
+        let mut checked = vec![0; self.height()];
+        let mut next = 0;
+        while let Some(pos) = unchecked.get(next).cloned() {
+            next += 1;
+            let Some((y, bit)) = self.locate(&pos) else {
+                continue;
+            };
+            if checked[y] & bit != 0 {
+                continue;
+            }
+            checked[y] |= bit;
+            let Some(colour) = self.colour_at(y, bit) else {
+                continue;
+            };
+            let mut runs = Vec::new();
+            let (start, len) = self.run_across(colour, y, bit);
+            if len >= 3 {
+                runs.push(
+                    (start..start + len)
+                        .map(|x| self.position(x as i32, y))
+                        .collect::<Vec<_>>(),
+                );
+            }
+            let (start, len) = self.run_down(colour, y, bit);
+            if len >= 3 {
+                let x = bit.trailing_zeros() as i32;
+                runs.push((start..start + len).map(|y| self.position(x, y)).collect());
+            }
+            for run in runs {
+                if !lines.contains(&run) {
+                    unchecked.extend(run.iter().cloned());
+                    lines.push(run);
+                }
+            }
+        }
+        merge_intersecting_lines(&lines)
+    }
+
+    /// Whether swapping the two cells would make a match, leaving the bitboard as it was. Same
+    /// as [`crate::token_grid::check_for_matches`] after the swap finding anything, without
+    /// building the matches.
+    pub fn swap_makes_match(&mut self, pos_a: &Position, pos_b: &Position) -> bool {
+        self.swap(pos_a, pos_b);
+        let makes_match = [pos_a, pos_b].iter().any(|pos| {
+            self.locate(pos)
+                .is_some_and(|(y, bit)| self.has_run_through(y, bit))
+        });
+        self.swap(pos_a, pos_b);
+        makes_match
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rng::GameRng;
+    use crate::token_grid;
+    use rand::Rng;
+
+    #[test]
+    fn test_matches_hash_map() {
+        let mut rng = GameRng::from_seed(1);
+        for _ in 0..200 {
+            // Few colours and a few holes and bombs so there's plenty to match
+            let mut pieces = HashMap::new();
+            for y in 0..8 {
+                for x in 0..9 {
+                    let type_ = match rng.gen_range(0..20) {
+                        0 => continue,
+                        1 => TokenType::Bomb,
+                        n => TokenType::ALL_REGULAR[n % 3],
+                    };
+                    pieces.insert(Position::new(x + 2, y - 1), Piece::new(type_));
+                }
+            }
+            let mut bits = Bitboard::from_pieces(&pieces).unwrap();
+
+            let sorted = |mut groups: Vec<(Vec<Position>, MatchKind)>| {
+                for (group, _) in groups.iter_mut() {
+                    group.sort_by_key(|pos| (pos.y, pos.x));
+                }
+                groups.sort_by_key(|(group, _)| (group[0].y, group[0].x));
+                groups
+            };
+            assert_eq!(
+                sorted(bits.check_entire_grid()),
+                sorted(token_grid::hash_map_check_entire_grid(&pieces))
+            );
+
+            let from = Position::new(rng.gen_range(2..11), rng.gen_range(-1..7));
+            for to in [
+                Position::new(from.x + 1, from.y),
+                Position::new(from.x, from.y + 1),
+            ] {
+                if !pieces.contains_key(&from) || !pieces.contains_key(&to) {
+                    continue;
+                }
+                let mut swapped = pieces.clone();
+                let piece = swapped.remove(&from).unwrap();
+                let other = swapped.insert(to.clone(), piece).unwrap();
+                swapped.insert(from.clone(), other);
+                let dirty = [from.clone(), to.clone()];
+                let expected = token_grid::hash_map_check_for_matches(&swapped, &dirty);
+                assert_eq!(bits.swap_makes_match(&from, &to), !expected.is_empty());
+                assert_eq!(
+                    Bitboard::around_swap(&pieces, &from, &to).swap_makes_match(&from, &to),
+                    !expected.is_empty()
+                );
+                assert_eq!(
+                    token_grid::hash_map_swap_makes_match(&pieces, &from, &to),
+                    !expected.is_empty()
+                );
+                let swapped_bits = Bitboard::from_pieces(&swapped).unwrap();
+                assert_eq!(swapped_bits.check_for_matches(&dirty), expected);
+            }
+        }
+
+        let wide = (0..=MAX_WIDTH)
+            .map(|x| (Position::new(x, 0), Piece::new(TokenType::Red)))
+            .collect::<HashMap<_, _>>();
+        assert!(Bitboard::from_pieces(&wide).is_none());
+    }
+}
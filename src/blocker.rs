@@ -0,0 +1,45 @@
+use serde::{Deserialize, Serialize};
+
+/// Obstacles living in a cell alongside (or instead of) a piece
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub enum BlockerKind {
+    // Sits under a piece and cracks whenever the piece on it is cleared
+    Ice,
+    // Fills the cell so no piece can be there, broken by matches next to it
+    Stone,
+    // Locks the piece in its cell so it can't be swapped or fall, broken by clearing that piece
+    Chain,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Blocker {
+    pub kind: BlockerKind,
+    // Hits left before it breaks
+    pub hits: u32,
+}
+
+impl Blocker {
+    pub fn new(kind: BlockerKind, hits: u32) -> Self {
+        Self { kind, hits }
+    }
+
+    /// Take one hit, returning true if that broke it
+    pub fn hit(&mut self) -> bool {
+        self.hits = self.hits.saturating_sub(1);
+        self.is_broken()
+    }
+
+    pub fn is_broken(&self) -> bool {
+        self.hits == 0
+    }
+
+    /// Whether a piece can ever sit in the blocker's cell
+    pub fn holds_piece(&self) -> bool {
+        self.kind != BlockerKind::Stone
+    }
+
+    /// Whether the blocker keeps the cell's contents where they are, i.e. gravity passes it by
+    pub fn is_immovable(&self) -> bool {
+        matches!(self.kind, BlockerKind::Stone | BlockerKind::Chain)
+    }
+}
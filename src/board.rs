@@ -0,0 +1,762 @@
+use crate::blocker::{Blocker, BlockerKind};
+use crate::core::{MatchKind, Position};
+use crate::piece::{Piece, Special, TokenType};
+use crate::shape::BoardShape;
+use crate::special::ComboEffect;
+use crate::{special, token_grid};
+use hashbrown::{HashMap, HashSet};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::vec_deque::VecDeque;
+
+/// A piece moving from one position to another as the result of gravity or a refill. Refilled
+/// pieces start above the board, i.e. with a negative y.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct PieceMove {
+    pub from: Position,
+    pub to: Position,
+}
+
+impl PieceMove {
+    /// Number of tiles travelled, used to scale animation times
+    pub fn distance(&self) -> i32 {
+        (self.to.x - self.from.x).abs() + (self.to.y - self.from.y).abs()
+    }
+
+    pub fn is_spawn(&self) -> bool {
+        self.from.y < 0
+    }
+}
+
+/// Everything that happened when matches and specials were cleared off the board
+#[derive(Debug, Clone, Default)]
+pub struct ClearResult {
+    pub matches: Vec<(Vec<Position>, MatchKind)>,
+    pub removed: Vec<(Position, Piece)>,
+    pub detonations: Vec<(Position, Special)>,
+    // Positions of special tokens earned by the matches
+    pub created: Vec<Position>,
+    // Blockers that took a hit, as they are after it. Those with no hits left have been removed.
+    pub damaged: Vec<(Position, Blocker)>,
+    // Ingredients that reached the bottom of the board and were taken off it
+    pub collected: Vec<Position>,
+}
+
+impl ClearResult {
+    pub fn is_empty(&self) -> bool {
+        self.removed.is_empty()
+            && self.created.is_empty()
+            && self.damaged.is_empty()
+            && self.collected.is_empty()
+    }
+}
+
+/// Positions queued up for removal while resolving a clear, and the specials they set off
+#[derive(Debug, Default)]
+struct Clearing {
+    to_remove: Vec<Position>,
+    removing: HashSet<Position>,
+    triggers: VecDeque<(Position, Option<TokenType>)>,
+    detonations: Vec<(Position, Special)>,
+}
+
+impl Clearing {
+    /// Queue `pos` for removal without setting off its special
+    fn consume(&mut self, pos: &Position) -> bool {
+        let is_new = self.removing.insert(pos.clone());
+        if is_new {
+            self.to_remove.push(pos.clone());
+        }
+        is_new
+    }
+
+    /// Queue `pos` for removal, setting off its special if it has one
+    fn remove(&mut self, board: &Board, pos: &Position) {
+        if self.consume(pos) && board.get(pos).is_some_and(|piece| piece.special.is_some()) {
+            self.triggers.push_back((pos.clone(), None));
+        }
+    }
+
+    /// Queue `pos` for removal and set off its special, aimed at `colour` for colour bombs
+    fn trigger(&mut self, pos: &Position, colour: Option<TokenType>) {
+        if self.consume(pos) {
+            self.triggers.push_back((pos.clone(), colour));
+        }
+    }
+}
+
+/// The pure-logic game board. Nothing in here knows about windows, textures or time, so it can
+/// be driven by the renderer, tests or a headless simulation alike.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Board {
+    pub shape: BoardShape,
+    pub pieces: HashMap<Position, Piece>,
+    pub blockers: HashMap<Position, Blocker>,
+    // Colours new pieces are drawn from
+    pub colours: Vec<TokenType>,
+    // Ingredients still to drop in. One is spawned by a refill whenever none are on the board.
+    pub ingredients_to_spawn: u32,
+}
+
+impl Board {
+    pub fn new(pieces: HashMap<Position, Piece>, shape: BoardShape) -> Self {
+        Self {
+            shape,
+            pieces,
+            blockers: HashMap::new(),
+            colours: TokenType::ALL_REGULAR.to_vec(),
+            ingredients_to_spawn: 0,
+        }
+    }
+
+    pub fn get(&self, pos: &Position) -> Option<&Piece> {
+        self.pieces.get(pos)
+    }
+
+    pub fn contains(&self, pos: &Position) -> bool {
+        self.pieces.contains_key(pos)
+    }
+
+    pub fn remove(&mut self, pos: &Position) -> Option<Piece> {
+        self.pieces.remove(pos)
+    }
+
+    /// The piece at `pos` is chained in place and can't be swapped
+    pub fn is_locked(&self, pos: &Position) -> bool {
+        self.blockers
+            .get(pos)
+            .is_some_and(|blocker| blocker.kind == BlockerKind::Chain)
+    }
+
+    /// Gravity passes `pos` by, whatever is in it stays put
+    fn is_immovable(&self, pos: &Position) -> bool {
+        self.blockers.get(pos).is_some_and(Blocker::is_immovable)
+    }
+
+    pub fn swap(&mut self, pos_a: &Position, pos_b: &Position) {
+        let piece_a = self.pieces.remove(pos_a).unwrap();
+        let piece_b = self.pieces.remove(pos_b).unwrap();
+        self.pieces.insert(pos_b.clone(), piece_a);
+        self.pieces.insert(pos_a.clone(), piece_b);
+    }
+
+    pub fn is_valid_swap(&self, pos1: &Position, pos2: &Position) -> bool {
+        if !pos1.is_adjacent(pos2) || !self.contains(pos1) || !self.contains(pos2) {
+            return false;
+        }
+        if self.is_locked(pos1) || self.is_locked(pos2) {
+            return false;
+        }
+        // Bombs and colour bombs can always be swapped, that's how they are set off, as can any
+        // two specials together
+        if self.is_always_swappable(pos1, pos2) {
+            return true;
+        }
+
+        // Otherwise must trigger a match, checked without touching the board
+        token_grid::swap_makes_match(&self.pieces, pos1, pos2)
+    }
+
+    pub(crate) fn is_always_swappable(&self, pos1: &Position, pos2: &Position) -> bool {
+        [pos1, pos2]
+            .iter()
+            .any(|pos| self.get(pos).is_some_and(Piece::is_swap_activated))
+            || self.is_special_combo(pos1, pos2)
+    }
+
+    /// Clear everything set off by the (already made) swap of `pos_a` and `pos_b`: bombs and
+    /// colour bombs that were swapped, and any matches the swap made. Swapping two specials
+    /// together is handled by [`Board::resolve_combo`] instead.
+    pub fn resolve_swap(&mut self, pos_a: &Position, pos_b: &Position) -> ClearResult {
+        let mut clearing = Clearing::default();
+        for (pos, other) in [(pos_a, pos_b), (pos_b, pos_a)] {
+            if self.get(pos).is_some_and(Piece::is_swap_activated) {
+                let colour = self
+                    .get(other)
+                    .filter(|piece| piece.is_matchable())
+                    .map(|piece| piece.type_);
+                clearing.trigger(pos, colour);
+            }
+        }
+        let swapped = [pos_a.clone(), pos_b.clone()];
+        let matches = token_grid::check_for_matches(&self.pieces, &swapped);
+        self.clear(clearing, matches, &swapped)
+    }
+
+    /// Both swapped tokens are specials, so they go off together as a combined effect centred
+    /// on `pos_b`
+    pub fn is_special_combo(&self, pos_a: &Position, pos_b: &Position) -> bool {
+        [pos_a, pos_b]
+            .iter()
+            .all(|pos| self.get(pos).is_some_and(|piece| piece.special.is_some()))
+    }
+
+    /// Set off the combined effect of the (already made) swap of two specials
+    pub fn resolve_combo(&mut self, pos_a: &Position, pos_b: &Position) -> ClearResult {
+        let mut clearing = Clearing::default();
+        let (piece_a, piece_b) = (self.get(pos_a).unwrap(), self.get(pos_b).unwrap());
+        let effect = special::combo_effect(self, pos_b, piece_a, piece_b);
+        for pos in [pos_a, pos_b] {
+            if matches!(&effect, ComboEffect::Convert(positions, _) if positions.contains(pos)) {
+                // Converted along with the rest of its colour, so it goes off with them below
+                continue;
+            }
+            let special = self.get(pos).unwrap().special.unwrap();
+            clearing.detonations.push((pos.clone(), special));
+            clearing.consume(pos);
+        }
+
+        match effect {
+            ComboEffect::Clear(area) => {
+                for pos in area.iter() {
+                    clearing.remove(self, pos);
+                }
+            }
+            ComboEffect::Convert(positions, special) => {
+                for pos in positions.iter() {
+                    if self.is_locked(pos) {
+                        // Chained pieces keep their shape, the chain takes the hit instead
+                        clearing.remove(self, pos);
+                        continue;
+                    }
+                    if let Some(piece) = self.pieces.get_mut(pos) {
+                        *piece = Piece::new_special(piece.type_, special);
+                    }
+                    clearing.trigger(pos, None);
+                }
+            }
+        }
+        self.clear(clearing, Vec::new(), &[])
+    }
+
+    /// Clear every match on the board, e.g. after gravity has moved things around, and take off
+    /// any ingredients that have reached the bottom
+    pub fn resolve_whole_grid(&mut self) -> ClearResult {
+        let matches = token_grid::check_entire_grid(&self.pieces);
+        let mut clear_result = self.clear(Clearing::default(), matches, &[]);
+        clear_result.collected = self.collect_ingredients();
+        clear_result
+    }
+
+    /// Remove every ingredient sitting in the lowest cell gravity can reach in its column
+    fn collect_ingredients(&mut self) -> Vec<Position> {
+        let mut collected = self
+            .pieces
+            .iter()
+            .filter(|(_, piece)| piece.is_ingredient())
+            .map(|(pos, _)| pos.clone())
+            .filter(|pos| {
+                self.shape
+                    .column_bottom_up(pos.x)
+                    .into_iter()
+                    .find(|cell| !self.is_immovable(cell))
+                    .as_ref()
+                    == Some(pos)
+            })
+            .collect::<Vec<_>>();
+        collected.sort_by_key(|pos| pos.x);
+        for pos in collected.iter() {
+            self.pieces.remove(pos);
+        }
+        collected
+    }
+
+    /// Remove matched tokens along with anything already queued in `clearing`, set off any
+    /// specials caught up in them (chaining into further specials), then place the specials
+    /// earned by big matches. New specials go on one of `preferred_spawns` if it is part of the
+    /// match, otherwise as near the middle of it as they can without landing on a chained piece.
+    ///
+    /// Blockers take their hits here too: chained pieces lose a chain instead of being removed,
+    /// ice cracks under removed pieces, and stones crack next to matches.
+    fn clear(
+        &mut self,
+        mut clearing: Clearing,
+        matches: Vec<(Vec<Position>, MatchKind)>,
+        preferred_spawns: &[Position],
+    ) -> ClearResult {
+        let mut created = Vec::new();
+        let mut unique_matches = Vec::new();
+
+        for (group, kind) in matches {
+            let colour = self.get(&group[0]).unwrap().type_;
+            if let Some(special) = special::special_for_match(kind, &group) {
+                // Chained pieces aren't removed, so the special can't go in their place
+                let middle = group.len() / 2;
+                let spawn_pos = preferred_spawns
+                    .iter()
+                    .find(|pos| group.contains(pos) && !self.is_locked(pos))
+                    .or_else(|| {
+                        group
+                            .iter()
+                            .enumerate()
+                            .filter(|(_, pos)| !self.is_locked(pos))
+                            .min_by_key(|(i, _)| i.abs_diff(middle))
+                            .map(|(_, pos)| pos)
+                    });
+                if let Some(spawn_pos) = spawn_pos {
+                    created.push((spawn_pos.clone(), Piece::new_special(colour, special)));
+                }
+            }
+            for pos in group.iter() {
+                clearing.remove(self, pos);
+            }
+            unique_matches.push((group, kind));
+        }
+
+        while let Some((pos, colour)) = clearing.triggers.pop_front() {
+            if self.is_locked(&pos) {
+                // Chained specials stay put, so they don't go off either
+                continue;
+            }
+            let Some(special) = self.get(&pos).and_then(|piece| piece.special) else {
+                continue;
+            };
+            clearing.detonations.push((pos.clone(), special));
+            for blasted in special::blast_area(self, &pos, special, colour) {
+                clearing.remove(self, &blasted);
+            }
+        }
+
+        let mut hit = Vec::new();
+        let mut stones = self
+            .blockers
+            .iter()
+            .filter(|(pos, blocker)| {
+                blocker.kind == BlockerKind::Stone
+                    && unique_matches
+                        .iter()
+                        .any(|(group, _)| group.iter().any(|other| pos.is_adjacent(other)))
+            })
+            .map(|(pos, _)| pos.clone())
+            .collect::<Vec<_>>();
+        stones.sort_by_key(|pos| (pos.y, pos.x));
+        hit.extend(stones);
+
+        let mut removed = Vec::new();
+        for pos in clearing.to_remove {
+            if self.get(&pos).is_some_and(Piece::is_ingredient) {
+                // Caught in a blast, but ingredients can only leave from the bottom
+                continue;
+            }
+            if self.is_locked(&pos) {
+                hit.push(pos);
+                continue;
+            }
+            if let Some(piece) = self.pieces.remove(&pos) {
+                if self
+                    .blockers
+                    .get(&pos)
+                    .is_some_and(|blocker| blocker.kind == BlockerKind::Ice)
+                {
+                    hit.push(pos.clone());
+                }
+                removed.push((pos, piece));
+            }
+        }
+        let damaged = hit
+            .into_iter()
+            .filter_map(|pos| {
+                let blocker = self.blockers.get_mut(&pos)?;
+                if blocker.hit() {
+                    let blocker = self.blockers.remove(&pos)?;
+                    Some((pos, blocker))
+                } else {
+                    Some((pos, *blocker))
+                }
+            })
+            .collect();
+        let created = created
+            .into_iter()
+            .map(|(pos, piece)| {
+                self.pieces.insert(pos.clone(), piece);
+                pos
+            })
+            .collect();
+
+        ClearResult {
+            matches: unique_matches,
+            removed,
+            detonations: clearing.detonations,
+            created,
+            damaged,
+            collected: Vec::new(),
+        }
+    }
+
+    /// Make a swap and play it out completely: the swap's own clear, then gravity, refills and
+    /// every cascade after them until the board settles. Returns each clear in order. This is the
+    /// headless equivalent of the game's phase machine.
+    pub fn play_move<R: Rng + ?Sized>(
+        &mut self,
+        pos_a: &Position,
+        pos_b: &Position,
+        rng: &mut R,
+    ) -> Vec<ClearResult> {
+        let is_combo = self.is_special_combo(pos_a, pos_b);
+        self.swap(pos_a, pos_b);
+        let mut clear_result = if is_combo {
+            self.resolve_combo(pos_a, pos_b)
+        } else {
+            self.resolve_swap(pos_a, pos_b)
+        };
+        let mut clears = Vec::new();
+        while !clear_result.is_empty() {
+            clears.push(clear_result);
+            self.gravity_refill(rng);
+            clear_result = self.resolve_whole_grid();
+        }
+        clears
+    }
+
+    /// Drop every piece down to fill the gaps beneath it, then spawn new random pieces above the
+    /// board to fill whatever is left, one of them an ingredient if any are due. Pieces fall
+    /// straight past any holes in the board's shape, and past stones and chained pieces, which
+    /// stay where they are. Returns every piece that moved, spawned pieces included.
+    pub fn gravity_refill<R: Rng + ?Sized>(&mut self, rng: &mut R) -> Vec<PieceMove> {
+        let mut moves = Vec::new();
+        // Kept in column order so refills draw from the rng in the same order every time
+        let mut gaps_by_x = Vec::new();
+
+        for x in 0i32..(self.shape.width as i32) {
+            let mut column = self.shape.column_bottom_up(x);
+            column.retain(|pos| !self.is_immovable(pos));
+            let mut known_gaps = VecDeque::new();
+            for pos in column.iter().cloned() {
+                if !self.pieces.contains_key(&pos) {
+                    known_gaps.push_back(pos);
+                } else {
+                    //There is a token here, if we have any known gaps, move this token down to fill it
+                    // and don't remember to treat this as a gap to fill now it's moved
+                    if let Some(gap_pos) = known_gaps.pop_front() {
+                        if let Some(piece) = self.pieces.remove(&pos) {
+                            self.pieces.insert(gap_pos.clone(), piece);
+                            moves.push(PieceMove {
+                                from: pos.clone(),
+                                to: gap_pos,
+                            });
+                        }
+                        known_gaps.push_back(pos);
+                    }
+                }
+            }
+            // record unfilled gaps, we'll need to spawn that many tokens
+            gaps_by_x.push((x, known_gaps.into_iter().collect::<Vec<_>>()));
+        }
+
+        // We we moved tokens down, we need to spawn new ones above
+        for (x, gaps) in gaps_by_x.iter() {
+            // Gaps left over are the top cells of the column, lowest first. The lowest is filled
+            // by the token spawned just above the board, the next by the one above that, etc.
+            for (i, pos_below) in gaps.iter().cloned().enumerate() {
+                let pos_above = Position::new(*x, -(i as i32) - 1);
+                let new_token_type = *self.colours.choose(rng).unwrap();
+                self.pieces
+                    .insert(pos_below.clone(), Piece::new(new_token_type));
+                moves.push(PieceMove {
+                    from: pos_above,
+                    to: pos_below,
+                });
+            }
+        }
+
+        let spawned = moves
+            .iter()
+            .filter(|m| m.is_spawn())
+            .map(|m| m.to.clone())
+            .collect::<Vec<_>>();
+        if self.ingredients_to_spawn > 0
+            && !spawned.is_empty()
+            && !self.pieces.values().any(Piece::is_ingredient)
+        {
+            let pos = &spawned[rng.gen_range(0..spawned.len())];
+            self.pieces
+                .insert(pos.clone(), Piece::new(TokenType::Ingredient));
+            self.ingredients_to_spawn -= 1;
+        }
+        moves
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::piece::LineDirection::{Horizontal, Vertical};
+    use crate::rng::GameRng;
+    use crate::test_util::board_from_rows;
+
+    #[test]
+    fn test_valid_swap() {
+        let board = board_from_rows(&["RGB", "GRB", "RBG"]);
+        assert!(board.is_valid_swap(&Position::new(0, 1), &Position::new(1, 1)));
+        assert!(!board.is_valid_swap(&Position::new(0, 0), &Position::new(1, 0)));
+        assert!(!board.is_valid_swap(&Position::new(0, 1), &Position::new(2, 1)));
+        // Checking a swap must leave the board untouched
+        assert_eq!(
+            board.get(&Position::new(0, 1)).unwrap().type_,
+            TokenType::Green
+        );
+    }
+
+    #[test]
+    fn test_gravity_refill() {
+        let mut board = board_from_rows(&["RGB", "G.B", "R.G"]);
+        let moves = board.gravity_refill(&mut GameRng::from_seed(1));
+
+        assert_eq!(board.pieces.len(), 9);
+        assert_eq!(
+            board.get(&Position::new(1, 2)).unwrap().type_,
+            TokenType::Green
+        );
+        assert!(moves.contains(&PieceMove {
+            from: Position::new(1, 0),
+            to: Position::new(1, 2)
+        }));
+        let spawns = moves.iter().filter(|m| m.is_spawn()).collect::<Vec<_>>();
+        assert_eq!(spawns.len(), 2);
+        assert!(spawns.iter().all(|m| m.distance() == 2));
+    }
+
+    #[test]
+    fn test_gravity_refill_masked() {
+        // Column 1 has a hole in the middle, the green above it drops past to the bottom
+        let mut board = board_from_rows(&["RGB", "Y.P", "R.G"]);
+        board.shape = BoardShape::from_rows(&["###", "#.#", "###"]);
+        let moves = board.gravity_refill(&mut GameRng::from_seed(1));
+
+        assert_eq!(board.pieces.len(), 8);
+        assert!(!board.contains(&Position::new(1, 1)));
+        assert_eq!(
+            board.get(&Position::new(1, 2)).unwrap().type_,
+            TokenType::Green
+        );
+        assert_eq!(moves.iter().filter(|m| m.is_spawn()).count(), 1);
+        assert!(moves.contains(&PieceMove {
+            from: Position::new(1, -1),
+            to: Position::new(1, 0)
+        }));
+    }
+
+    #[test]
+    fn test_blockers() {
+        // Stone at (3, 0), ice under (2, 1), chain on (0, 1)
+        let mut board = board_from_rows(&["RGB.", "GRBY", "RBGY", "YPRG"]);
+        board
+            .blockers
+            .insert(Position::new(3, 0), Blocker::new(BlockerKind::Stone, 1));
+        board
+            .blockers
+            .insert(Position::new(2, 1), Blocker::new(BlockerKind::Ice, 2));
+        board
+            .blockers
+            .insert(Position::new(0, 1), Blocker::new(BlockerKind::Chain, 1));
+
+        // Chained pieces can't be swapped
+        assert!(!board.is_valid_swap(&Position::new(0, 1), &Position::new(0, 0)));
+
+        // Blues in column 2 make a match next to the stone and through the ice
+        let (a, b) = (Position::new(1, 2), Position::new(2, 2));
+        assert!(board.is_valid_swap(&a, &b));
+        board.swap(&a, &b);
+        let result = board.resolve_swap(&a, &b);
+        assert_eq!(result.removed.len(), 3);
+        assert_eq!(
+            result.damaged,
+            vec![
+                (Position::new(3, 0), Blocker::new(BlockerKind::Stone, 0)),
+                (Position::new(2, 1), Blocker::new(BlockerKind::Ice, 1)),
+            ]
+        );
+        assert!(!board.blockers.contains_key(&Position::new(3, 0)));
+
+        // The stone is gone so its cell refills, the chained green stays put
+        board.gravity_refill(&mut GameRng::from_seed(1));
+        assert_eq!(board.pieces.len(), 16);
+        assert_eq!(
+            board.get(&Position::new(0, 1)).unwrap().type_,
+            TokenType::Green
+        );
+    }
+
+    #[test]
+    fn test_ingredients() {
+        let mut board = board_from_rows(&["RGB", "GBR", "BRG"]);
+        board.ingredients_to_spawn = 2;
+        board.remove(&Position::new(0, 2));
+        board.gravity_refill(&mut GameRng::from_seed(1));
+        // Only one spawned piece so it has to be the ingredient
+        assert_eq!(
+            board.get(&Position::new(0, 0)).unwrap().type_,
+            TokenType::Ingredient
+        );
+        assert_eq!(board.ingredients_to_spawn, 1);
+
+        // Blasts pass over it
+        let mut clearing = Clearing::default();
+        clearing.trigger(&Position::new(0, 0), None);
+        let result = board.clear(clearing, Vec::new(), &[]);
+        assert!(result.removed.is_empty());
+
+        // Dropped to the bottom it gets collected
+        board.swap(&Position::new(0, 0), &Position::new(0, 2));
+        let result = board.resolve_whole_grid();
+        assert_eq!(result.collected, vec![Position::new(0, 2)]);
+        assert!(!board.contains(&Position::new(0, 2)));
+    }
+
+    #[test]
+    fn test_match_four_makes_line_clear() {
+        let mut board = board_from_rows(&["RRGR", "GBRB", "BYBY"]);
+        let (a, b) = (Position::new(2, 0), Position::new(2, 1));
+        assert!(board.is_valid_swap(&a, &b));
+        board.swap(&a, &b);
+        let result = board.resolve_swap(&a, &b);
+
+        assert_eq!(result.removed.len(), 4);
+        assert_eq!(result.created, vec![a.clone()]);
+        let created = board.get(&a).unwrap();
+        assert_eq!(created.type_, TokenType::Red);
+        assert_eq!(created.special, Some(Special::LineClear(Horizontal)));
+    }
+
+    #[test]
+    fn test_match_four_around_chains() {
+        let mut board = board_from_rows(&["RRRR", "GBGB", "BGBG"]);
+        for x in 1..=2 {
+            board
+                .blockers
+                .insert(Position::new(x, 0), Blocker::new(BlockerKind::Chain, 2));
+        }
+        let result = board.resolve_whole_grid();
+
+        // The chained reds stay, losing a chain each, and the special goes on a free end
+        assert_eq!(result.removed.len(), 2);
+        assert_eq!(result.damaged.len(), 2);
+        assert_eq!(result.created.len(), 1);
+        let spawn_pos = &result.created[0];
+        assert!(spawn_pos.x == 0 || spawn_pos.x == 3);
+        assert!(board.get(spawn_pos).unwrap().special.is_some());
+        for x in 1..=2 {
+            let chained = board.get(&Position::new(x, 0)).unwrap();
+            assert_eq!((chained.type_, chained.special), (TokenType::Red, None));
+        }
+    }
+
+    #[test]
+    fn test_swapped_specials_detonate() {
+        let mut board = board_from_rows(&["RGBR", "GbGY", "RGBY", "YRGB"]);
+        let (a, b) = (Position::new(1, 1), Position::new(1, 2));
+        assert!(board.is_valid_swap(&a, &b));
+        board.swap(&a, &b);
+        let result = board.resolve_swap(&a, &b);
+        // Bomb is now at (1, 2), clearing (0..=2, 1..=3)
+        assert_eq!(result.detonations, vec![(b.clone(), Special::Bomb)]);
+        assert_eq!(result.removed.len(), 9);
+
+        let mut board = board_from_rows(&["RGBR", "GcGY", "RGBY"]);
+        let (a, b) = (Position::new(1, 1), Position::new(1, 0));
+        board.swap(&a, &b);
+        let result = board.resolve_swap(&a, &b);
+        // Swapped with green, so all four greens go along with the colour bomb
+        assert_eq!(result.removed.len(), 5);
+        assert!(board
+            .pieces
+            .values()
+            .all(|piece| piece.type_ != TokenType::Green));
+    }
+
+    #[test]
+    fn test_special_combos() {
+        // Two line clearers clear both the row and column through the second position
+        let mut board = board_from_rows(&["GBYP", "BhvG", "YPGB", "PGBY"]);
+        let (a, b) = (Position::new(1, 1), Position::new(2, 1));
+        assert!(board.is_valid_swap(&a, &b));
+        board.swap(&a, &b);
+        let result = board.resolve_combo(&a, &b);
+        assert_eq!(result.removed.len(), 7);
+        assert!(board.pieces.keys().all(|pos| pos.x != 2 && pos.y != 1));
+
+        // Colour bomb with a red line clearer turns every red into a line clearer
+        let mut board = board_from_rows(&["RGBY", "GchB", "YBGR"]);
+        let (a, b) = (Position::new(1, 1), Position::new(2, 1));
+        board.swap(&a, &b);
+        let result = board.resolve_combo(&a, &b);
+        let converted = result
+            .detonations
+            .iter()
+            .filter(|(_, special)| *special == Special::LineClear(Horizontal))
+            .count();
+        // The swapped line clearer and the two other reds
+        assert_eq!(converted, 3);
+
+        // The swapped line clearer fires its own line too, not just the converted reds
+        let mut board = board_from_rows(&["RGBY", "GcvB", "YBGR"]);
+        let (a, b) = (Position::new(2, 1), Position::new(1, 1));
+        board.swap(&a, &b);
+        let result = board.resolve_combo(&a, &b);
+        assert!(result
+            .detonations
+            .contains(&(b.clone(), Special::LineClear(Vertical))));
+        assert_eq!(result.detonations.len(), 4);
+        assert!(board.pieces.keys().all(|pos| pos.x != 1));
+        assert!(board.get(&Position::new(2, 0)).is_some());
+
+        // Same again with the top left red chained, which keeps its shape and loses a chain
+        let mut board = board_from_rows(&["RGBY", "GchB", "YBGR"]);
+        let chained = Position::new(0, 0);
+        board
+            .blockers
+            .insert(chained.clone(), Blocker::new(BlockerKind::Chain, 2));
+        let (a, b) = (Position::new(1, 1), Position::new(2, 1));
+        board.swap(&a, &b);
+        let result = board.resolve_combo(&a, &b);
+        let converted = result
+            .detonations
+            .iter()
+            .filter(|(_, special)| *special == Special::LineClear(Horizontal))
+            .count();
+        assert_eq!(converted, 2);
+        assert_eq!(
+            result.damaged,
+            vec![(chained.clone(), Blocker::new(BlockerKind::Chain, 1))]
+        );
+        let piece = board.get(&chained).unwrap();
+        assert_eq!((piece.type_, piece.special), (TokenType::Red, None));
+
+        // Two colour bombs clear everything
+        let mut board = board_from_rows(&["RGBY", "GccB", "YBGR"]);
+        let (a, b) = (Position::new(1, 1), Position::new(2, 1));
+        board.swap(&a, &b);
+        let result = board.resolve_combo(&a, &b);
+        assert_eq!(result.removed.len(), 12);
+        assert!(board.pieces.is_empty());
+    }
+
+    #[test]
+    fn test_same_seed_same_game() {
+        let play = |seed: u64| {
+            let mut board = board_from_rows(&["RGBYP", "GRBPY", "RBGYP", "YPRGB", "PYGBR"]);
+            let mut rng = GameRng::from_seed(seed);
+            let mut boards = Vec::new();
+            // Keep playing the first valid swap, recording each settled board
+            for _ in 0..5 {
+                let Some(valid_move) = crate::moves::find_valid_moves(&board).into_iter().next()
+                else {
+                    break;
+                };
+                board.play_move(&valid_move.from, &valid_move.to, &mut rng);
+                let mut snapshot = board
+                    .pieces
+                    .iter()
+                    .map(|(pos, piece)| (pos.y, pos.x, piece.clone()))
+                    .collect::<Vec<_>>();
+                snapshot.sort_by_key(|(y, x, _)| (*y, *x));
+                boards.push(snapshot);
+            }
+            boards
+        };
+        let first = play(42);
+        assert!(!first.is_empty());
+        assert_eq!(first, play(42));
+    }
+}
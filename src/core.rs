@@ -1,11 +1,8 @@
-use crate::{Phase, Token};
-use hashbrown::HashMap;
-use macroquad::math::{Rect, Vec2};
-use macroquad::texture::{load_image, Texture2D};
+use serde::{Deserialize, Serialize};
 
 const GRID_SIZE: i32 = 32;
 
-#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub struct Position {
     pub x: i32,
     pub y: i32,
@@ -36,22 +33,11 @@ impl Position {
         (x, y)
     }
 
-    pub fn lerp_to_world(&self, other: &Self, t: f32) -> (f32, f32) {
-        let (x, y) = self.to_world();
-        let (x2, y2) = other.to_world();
-        let x = x * (1.0 - t) + (x2 * t);
-        let y = y * (1.0 - t) + (y2 * t);
-        (x, y)
-    }
-
     pub fn from_world((x, y): (f32, f32)) -> Self {
         let x = (x / GRID_SIZE as f32).floor() as i32;
         let y = (y / GRID_SIZE as f32).floor() as i32;
         Self { x, y }
     }
-    pub fn from_world_vec2(vec: Vec2) -> Self {
-        Self::from_world((vec.x, vec.y))
-    }
 
     pub fn is_adjacent(&self, other: &Self) -> bool {
         let x = self.x - other.x;
@@ -60,60 +46,16 @@ impl Position {
     }
 
     pub fn neighbours(&self) -> Vec<Self> {
-        let mut positions = Vec::new();
-        positions.push(Self::new(self.x - 1, self.y));
-        positions.push(Self::new(self.x + 1, self.y));
-        positions.push(Self::new(self.x, self.y - 1));
-        positions.push(Self::new(self.x, self.y + 1));
-        positions
+        vec![
+            Self::new(self.x - 1, self.y),
+            Self::new(self.x + 1, self.y),
+            Self::new(self.x, self.y - 1),
+            Self::new(self.x, self.y + 1),
+        ]
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct AnimationPosition {
-    start: Position,
-    pub end: Position,
-    duration: f64,
-    start_time: Option<f64>,
-}
-
-impl AnimationPosition {
-    pub fn new_start(start: Position, end: Position, duration: f64) -> Self {
-        Self {
-            start,
-            end,
-            duration,
-            start_time: Some(macroquad::time::get_time()),
-        }
-    }
-    pub fn new(start: Position, end: Position, duration: f64) -> Self {
-        Self {
-            start,
-            end,
-            duration,
-            start_time: None,
-        }
-    }
-
-    pub fn start(&mut self) {
-        self.start_time = Some(macroquad::time::get_time());
-    }
-
-    pub fn is_done(&self) -> bool {
-        let now = macroquad::time::get_time();
-        let elapsed = now - self.start_time.unwrap_or(now);
-        elapsed > self.duration
-    }
-
-    pub fn get(&self) -> (f32, f32) {
-        let now = macroquad::time::get_time();
-        let elapsed = now - self.start_time.unwrap_or(now);
-        let t = (elapsed / self.duration).clamp(0., 1.);
-        self.start.lerp_to_world(&self.end, t as f32)
-    }
-}
-
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
 pub enum MatchKind {
     // i.e. XXX
     Three,
@@ -134,39 +76,3 @@ pub enum MatchKind {
     //        X
     SuperTShape,
 }
-
-#[derive(Clone, Debug)]
-pub struct TextureAtlas {
-    textures: Vec<Texture2D>,
-    pub texture_size: (f32, f32),
-}
-
-impl TextureAtlas {
-    pub async fn new(filename: &str, texture_size: (f32, f32), rows: i32, cols: i32) -> Self {
-        let rect = Rect::new(0f32, 0f32, texture_size.0, texture_size.1);
-        let image = load_image(filename).await.unwrap();
-        let mut textures = Vec::new();
-        for col in 0..cols {
-            for row in 0..rows {
-                let rect = Rect::new(
-                    rect.x + (col as f32 * rect.w),
-                    rect.y + (row as f32 * rect.h),
-                    rect.w,
-                    rect.h,
-                );
-                let texture = Texture2D::from_image(&image.sub_image(rect));
-                textures.push(texture);
-            }
-        }
-        Self {
-            textures,
-            texture_size,
-        }
-    }
-    pub fn get(&self, index: usize) -> Texture2D {
-        self.textures[index]
-    }
-    pub fn size(&self) -> usize {
-        self.textures.len()
-    }
-}
@@ -1,9 +1,9 @@
-use crate::animated_item::{AnimatedItem, AnimationScheme};
-use crate::core::{Position, TextureAtlas};
+use crate::animated_item::{AnimatedItem, AnimationScheme, TextureAtlas};
 use crate::token;
 use hashbrown::HashMap;
 use macroquad::audio::{load_sound, play_sound, PlaySoundParams, Sound};
-use macroquad::time::get_time;
+use matchup::blocker::BlockerKind;
+use matchup::core::Position;
 use strum::IntoEnumIterator;
 use strum_macros::EnumIter;
 
@@ -17,11 +17,16 @@ struct SoundEffect {
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, EnumIter)]
 pub enum EffectKind {
     Explosion,
+    IceCrack,
+    StoneCrack,
+    ChainBreak,
+    // A swap that isn't allowed, only heard
+    Denied,
 }
 
 #[derive(Debug)]
 pub struct EffectPlayer {
-    active_effects: HashMap<Position, AnimatedItem>,
+    active_effects: HashMap<(Position, EffectKind), AnimatedItem>,
     active_sounds: HashMap<EffectKind, f64>,
     effect_store: HashMap<EffectKind, AnimatedItem>,
     audio_store: HashMap<EffectKind, SoundEffect>,
@@ -39,13 +44,16 @@ impl EffectPlayer {
         }
     }
 
-    pub fn spawn_effect(&mut self, pos: Position, kind: EffectKind) {
-        let mut effect = self.effect_store.get(&kind).unwrap().clone();
-        effect.start();
-        self.active_effects.insert(pos, effect);
+    /// Start `kind` playing at `pos` at game time `now`
+    pub fn spawn_effect(&mut self, pos: Position, kind: EffectKind, now: f64) {
+        if let Some(effect) = self.effect_store.get(&kind) {
+            let mut effect = effect.clone();
+            effect.start(now);
+            self.active_effects.insert((pos, kind), effect);
+        }
         if let Some(sound) = self.audio_store.get(&kind) {
             let can_play = if let Some(already_playing_at) = self.active_sounds.get(&kind) {
-                let duration_played = get_time() - already_playing_at;
+                let duration_played = now - already_playing_at;
                 let pct_played = duration_played / sound.duration;
                 pct_played > 0.8
             } else {
@@ -60,27 +68,41 @@ impl EffectPlayer {
                         volume,
                     },
                 );
-                self.active_sounds.insert(kind, get_time());
+                self.active_sounds.insert(kind, now);
             }
         }
     }
 
-    pub fn update(&mut self) {
+    pub fn update(&mut self, now: f64) {
         self.active_effects.retain(|_, effect| {
-            effect.update();
+            effect.update(now);
             effect.is_playing()
         });
     }
 
+    /// Stop every effect that is playing
+    pub fn clear(&mut self) {
+        self.active_effects.clear();
+    }
+
     pub fn draw(&self) {
-        for (pos, effect) in self.active_effects.iter() {
+        for ((pos, _), effect) in self.active_effects.iter() {
             effect.draw(pos);
         }
     }
 }
 
 impl EffectKind {
-    async fn to_sound(&self) -> Option<SoundEffect> {
+    /// Effect for a blocker taking a hit
+    pub fn for_blocker(kind: BlockerKind) -> Self {
+        match kind {
+            BlockerKind::Ice => Self::IceCrack,
+            BlockerKind::Stone => Self::StoneCrack,
+            BlockerKind::Chain => Self::ChainBreak,
+        }
+    }
+
+    async fn to_sound(self) -> Option<SoundEffect> {
         match self {
             Self::Explosion => {
                 let sound = load_sound("res/audio/explosion.wav").await.unwrap();
@@ -90,6 +112,38 @@ impl EffectKind {
                     volume: 0.5,
                 })
             }
+            Self::Denied => {
+                let sound = load_sound("res/audio/denied.wav").await.unwrap();
+                Some(SoundEffect {
+                    sound,
+                    duration: 0.25,
+                    volume: 0.8,
+                })
+            }
+            Self::IceCrack => {
+                let sound = load_sound("res/audio/ice_crack.wav").await.unwrap();
+                Some(SoundEffect {
+                    sound,
+                    duration: 0.35,
+                    volume: 0.6,
+                })
+            }
+            Self::StoneCrack => {
+                let sound = load_sound("res/audio/stone_crack.wav").await.unwrap();
+                Some(SoundEffect {
+                    sound,
+                    duration: 0.45,
+                    volume: 0.8,
+                })
+            }
+            Self::ChainBreak => {
+                let sound = load_sound("res/audio/chain_break.wav").await.unwrap();
+                Some(SoundEffect {
+                    sound,
+                    duration: 0.45,
+                    volume: 0.5,
+                })
+            }
         }
     }
 }
@@ -107,14 +161,19 @@ async fn initialise_audio_store() -> HashMap<EffectKind, SoundEffect> {
 async fn initialise_effect_store() -> HashMap<EffectKind, AnimatedItem> {
     let mut store = HashMap::new();
 
-    let explosion = {
-        let atlas = TextureAtlas::new("res/explosion.png", (32f32, 32f32), 1, 6).await;
-        AnimatedItem::new(
+    for (kind, filename) in [
+        (EffectKind::Explosion, "res/explosion.png"),
+        (EffectKind::IceCrack, "res/ice_crack.png"),
+        (EffectKind::StoneCrack, "res/stone_crack.png"),
+        (EffectKind::ChainBreak, "res/chain_break.png"),
+    ] {
+        let atlas = TextureAtlas::new(filename, (32f32, 32f32), 1, 6).await;
+        let effect = AnimatedItem::new(
             atlas,
             true,
             AnimationScheme::TotalTime(token::ANIMATION_TIME_PER_TILE - 0.05),
-        )
-    };
-    store.insert(EffectKind::Explosion, explosion);
+        );
+        store.insert(kind, effect);
+    }
     store
 }
@@ -1,27 +1,568 @@
-use crate::effect_player::EffectPlayer;
-use crate::{Phase, Position, Token};
+use crate::effect_player::{EffectKind, EffectPlayer};
+use crate::phases;
+use crate::token::{Token, ANIMATION_TIME_PER_TILE};
+use crate::Phase;
 use hashbrown::HashMap;
+use matchup::board::{Board, ClearResult, PieceMove};
+use matchup::clock::GameClock;
+use matchup::core::Position;
+use matchup::history::{History, Snapshot, Step};
+use matchup::input::Selection;
+use matchup::level::Level;
+use matchup::mode::Mode;
+use matchup::moves::ValidMove;
+use matchup::objective::Objectives;
+use matchup::replay::{Playback, Replay, ReplayMove};
+use matchup::rng::GameRng;
+use matchup::save::{SaveGame, AUTOSAVE_INTERVAL};
+use matchup::score::Score;
+use matchup::solver::Bot;
+use matchup::tween::Easing;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+// Seconds without input before a hint is shown
+pub const DEFAULT_HINT_DELAY: f64 = 5.0;
+// Seconds a bot waits once the board settles before moving, so its moves can be followed
+pub const BOT_MOVE_DELAY: f64 = 0.5;
+// Seconds a denied swap takes to swing out and back
+pub const DENIED_SWAP_ANIMATION_TIME: f64 = 0.3;
+// Every token takes the same time to reach its place when the board is shuffled
+pub const SHUFFLE_ANIMATION_TIME: f64 = 0.6;
 
 pub struct GameState {
-    pub grid_size: usize,
+    pub level: Level,
+    pub board: Board,
+    // Visual state for each piece on the board, kept in step with `board.pieces`
     pub tokens: HashMap<Position, Token>,
-    pub selected_token_pos: Option<Position>,
+    // The token clicked or held down by the player
+    pub selection: Selection,
     pub phase: Phase,
     pub effect_player: EffectPlayer,
+    pub score: Score,
+    pub objectives: Objectives,
+    pub mode: Mode,
+    pub history: History,
+    pub rng: GameRng,
+    pub hint: Option<ValidMove>,
+    pub hint_delay: f64,
+    // Time of the last input, or of the board last settling
+    pub idle_since: f64,
+    // Every move made this game, kept in step with undo
+    pub recording: Replay,
+    // Where the recording is saved after each move, if anywhere
+    pub record_path: Option<PathBuf>,
+    // The replay being watched in place of taking input
+    pub playback: Option<Playback>,
+    // Plays in place of the player when there's no replay
+    pub bot: Option<Bot>,
+    // Seconds of game time and frames since the game started
+    pub play_time: f64,
+    pub frame: u64,
+    // Time every animation and effect runs on, which can be paused, slowed down or stepped
+    pub clock: GameClock,
+    // Where the game is saved to be resumed later, if anywhere
+    pub save_path: Option<PathBuf>,
+    // Swaps that make no match use up a move in move limited games
+    pub invalid_swap_costs_move: bool,
+    // When the last save was written and how many moves had been made by then
+    last_save: Option<(f64, usize)>,
 }
 
 impl GameState {
+    /// Start a level with a freshly generated board, or None if no playable board could be
+    /// generated for it
+    pub fn from_level(
+        level: Level,
+        mode: Mode,
+        effect_player: EffectPlayer,
+        mut rng: GameRng,
+    ) -> Option<Self> {
+        let recording = Replay::new(level.clone(), mode.kind, &rng);
+        let board = level.build_board(&mut rng)?;
+        let mut game_state = Self::new(level, mode, board, effect_player, rng);
+        game_state.recording = recording;
+        Some(game_state)
+    }
+
+    /// Start on an existing board. Its recording starts from the rng as given, so can only be
+    /// replayed on the same board.
     pub fn new(
-        tokens: HashMap<Position, Token>,
-        grid_size: usize,
+        level: Level,
+        mode: Mode,
+        board: Board,
         effect_player: EffectPlayer,
+        rng: GameRng,
     ) -> Self {
+        let tokens = board
+            .pieces
+            .keys()
+            .map(|pos| (pos.clone(), Token::new()))
+            .collect();
+        let objectives = Objectives::new(level.objectives.clone());
+        let recording = Replay::new(level.clone(), mode.kind, &rng);
         Self {
-            grid_size,
+            level,
+            board,
             tokens,
-            selected_token_pos: None,
+            selection: Selection::default(),
             phase: Phase::default(),
             effect_player,
+            score: Score::new(),
+            objectives,
+            mode,
+            history: History::default(),
+            rng,
+            hint: None,
+            hint_delay: DEFAULT_HINT_DELAY,
+            idle_since: 0.0,
+            recording,
+            record_path: None,
+            playback: None,
+            bot: None,
+            play_time: 0.0,
+            frame: 0,
+            clock: GameClock::new(),
+            save_path: None,
+            invalid_swap_costs_move: false,
+            last_save: None,
+        }
+    }
+
+    /// Carry on with a saved game. Undo history isn't saved so starts out empty.
+    pub fn from_save(save: SaveGame, effect_player: EffectPlayer) -> Self {
+        let SaveGame {
+            level,
+            snapshot,
+            recording,
+            play_time,
+            ..
+        } = save;
+        let mut game_state = Self::new(
+            level,
+            snapshot.mode,
+            snapshot.board,
+            effect_player,
+            snapshot.rng,
+        );
+        game_state.score = snapshot.score;
+        game_state.objectives = snapshot.objectives;
+        game_state.recording = recording;
+        game_state.play_time = play_time;
+        // Look the board over as if it had just settled, which also ends a game with no time left
+        game_state.phase = Phase::CheckWholeGrid;
+        game_state
+    }
+
+    pub fn to_save(&self) -> SaveGame {
+        SaveGame::new(
+            self.level.clone(),
+            self.snapshot(),
+            self.recording.clone(),
+            self.play_time,
+        )
+    }
+
+    /// Save every so often and after each move while waiting on the player, and get rid of the
+    /// save once the game is over as there's nothing left to resume
+    pub fn autosave(&mut self, now: f64) {
+        // Only the player's own games are worth coming back to
+        if self.playback.is_some() || self.bot.is_some() {
+            return;
+        }
+        match self.phase {
+            Phase::TakingInput => {
+                let moves = self.recording.moves.len();
+                let is_due = self.last_save.map_or(true, |(saved_at, saved_moves)| {
+                    moves != saved_moves || now - saved_at >= AUTOSAVE_INTERVAL
+                });
+                if is_due {
+                    self.write_save();
+                    self.last_save = Some((now, moves));
+                }
+            }
+            Phase::LevelWon(_) | Phase::LevelFailed if self.last_save.is_some() => {
+                if let Some(ref path) = self.save_path {
+                    println!("Game over, removing {}", path.display());
+                    let _ = std::fs::remove_file(path);
+                }
+                self.last_save = None;
+            }
+            _ => {}
+        }
+    }
+
+    /// Finish off any move in progress and save, for when the window is closing
+    pub fn save_on_exit(&mut self) {
+        self.settle();
+        // Far enough in the future that a save is due
+        self.autosave(f64::INFINITY);
+    }
+
+    fn write_save(&self) {
+        if let Some(ref path) = self.save_path {
+            if let Err(err) = self.to_save().save(path) {
+                println!("Couldn't save game to {}: {}", path.display(), err);
+            }
+        }
+    }
+
+    /// Play the level again from the start on a new board. Returns false, leaving everything as
+    /// it is, if no playable board could be generated.
+    pub fn restart(&mut self) -> bool {
+        let mut rng = self.rng.clone();
+        let Some(board) = self.level.build_board(&mut rng) else {
+            return false;
+        };
+        self.recording = Replay::new(self.level.clone(), self.mode.kind, &self.rng);
+        self.rng = rng;
+        self.play_time = 0.0;
+        self.frame = 0;
+        self.board = board;
+        self.tokens = self
+            .board
+            .pieces
+            .keys()
+            .map(|pos| (pos.clone(), Token::new()))
+            .collect();
+        self.selection.clear();
+        self.phase = Phase::default();
+        self.score = Score::new();
+        self.objectives = Objectives::new(self.level.objectives.clone());
+        self.mode = Mode::new(self.mode.kind);
+        self.history.clear();
+        self.hint = None;
+        true
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            board: self.board.clone(),
+            score: self.score.clone(),
+            objectives: self.objectives.clone(),
+            mode: self.mode.clone(),
+            rng: self.rng.clone(),
+        }
+    }
+
+    /// Remember the current state before the player swaps `pos_a` and `pos_b`
+    pub fn record_history(&mut self, pos_a: &Position, pos_b: &Position) {
+        if self.history.is_enabled(&self.mode) {
+            let snapshot = self.snapshot();
+            self.history
+                .record(snapshot, (pos_a.clone(), pos_b.clone()));
+        }
+    }
+
+    pub fn undo(&mut self) {
+        if !self.history.is_enabled(&self.mode) {
+            return;
+        }
+        let current = self.snapshot();
+        if let Some(step) = self.history.undo(current) {
+            println!("Undoing swap of {:?}", step.swap);
+            self.recording.unrecord();
+            self.save_recording();
+            self.restore(step, true);
+        }
+    }
+
+    pub fn redo(&mut self) {
+        if !self.history.is_enabled(&self.mode) {
+            return;
+        }
+        let current = self.snapshot();
+        if let Some(step) = self.history.redo(current) {
+            println!("Redoing swap of {:?}", step.swap);
+            // Still on the board the swap was made on, so it's denied again if it was then
+            let denied = !self.board.is_valid_swap(&step.swap.0, &step.swap.1);
+            self.record_move(&step.swap.0, &step.swap.1, denied);
+            self.restore(step, false);
+        }
+    }
+
+    /// Put the game back as it was in `step`, animating the tokens that changed. Undoing plays
+    /// the swap backwards and lifts everything else up from below the board, the reverse of it
+    /// falling in; redoing drops changed tokens in from above.
+    fn restore(&mut self, step: Step, undoing: bool) {
+        let Step { snapshot, swap } = step;
+        let old_board = std::mem::replace(&mut self.board, snapshot.board);
+        self.score = snapshot.score;
+        self.objectives = snapshot.objectives;
+        self.mode = snapshot.mode;
+        self.rng = snapshot.rng;
+
+        let height = self.board.shape.height as i32;
+        let now = self.clock.now();
+        self.tokens.clear();
+        for (pos, piece) in self.board.pieces.iter() {
+            let mut token = Token::new();
+            if old_board.get(pos) != Some(piece) {
+                let from = if undoing && *pos == swap.0 {
+                    swap.1.clone()
+                } else if undoing && *pos == swap.1 {
+                    swap.0.clone()
+                } else if undoing {
+                    Position::new(pos.x, pos.y + height)
+                } else {
+                    Position::new(pos.x, pos.y - height)
+                };
+                token.animate_move_to(
+                    from,
+                    pos.clone(),
+                    SHUFFLE_ANIMATION_TIME,
+                    Easing::EaseInOut,
+                    now,
+                );
+            }
+            self.tokens.insert(pos.clone(), token);
+        }
+        self.selection.clear();
+        self.hint = None;
+        self.phase = Phase::Animating(Rc::new(Phase::TakingInput));
+    }
+
+    /// Run the clocks for a frame of `dt` seconds of game time. The mode's clock only runs while
+    /// the board is waiting on a move, and holds at a replay's next move until that move is made.
+    ///
+    /// The mode's clock runs on game time rather than real time on purpose: replays are recorded
+    /// against it and watched faster by speeding up the game clock, and pausing or stepping the
+    /// game has to hold the countdown too. So slowing the game down gives a timed level more real
+    /// seconds, but it plays out exactly as it would at full speed.
+    pub fn tick_clock(&mut self, dt: f64) {
+        self.frame += 1;
+        self.play_time += dt;
+        tick_mode(&mut self.mode, &self.phase, self.playback.as_ref(), dt);
+    }
+
+    /// Add a swap that is about to be made to the recording, `denied` if it makes no match but
+    /// costs a move anyway
+    pub fn record_move(&mut self, pos_a: &Position, pos_b: &Position, denied: bool) {
+        self.recording.record(ReplayMove {
+            frame: self.frame,
+            time: self.play_time,
+            clock: self.mode.time_used(),
+            from: pos_a.clone(),
+            to: pos_b.clone(),
+            denied,
+        });
+        self.save_recording();
+    }
+
+    /// Write out the recording, unless a replay is being watched as that could be the very file
+    /// being played
+    fn save_recording(&self) {
+        if self.playback.is_some() {
+            return;
+        }
+        if let Some(ref path) = self.record_path {
+            if let Err(err) = self.recording.save(path) {
+                println!("Couldn't save replay to {}: {}", path.display(), err);
+            }
+        }
+    }
+
+    /// Bring the replay being watched to just after its `position`th move, by playing it again
+    /// from the start with animations skipped. Watching stops at the first move that isn't a
+    /// valid swap on the replayed board, as it does during playback.
+    pub fn seek(&mut self, position: usize) {
+        let Some(mut playback) = self.playback.take() else {
+            return;
+        };
+        let replay = playback.replay.clone();
+        self.level = replay.level.clone();
+        self.mode = Mode::new(replay.mode);
+        self.rng = replay.rng();
+        if !self.restart() {
+            println!("Couldn't rebuild the replay's starting board, stopping");
+            return;
+        }
+
+        let wanted = position.min(playback.len());
+        let volume = std::mem::replace(&mut self.effect_player.audio_effect_volume, 0.0);
+        let position = playback.seek(position, |replay_move| {
+            self.settle();
+            self.mode.tick(replay_move.clock - self.mode.time_used());
+            self.play_time = replay_move.time;
+            self.frame = replay_move.frame;
+            phases::replay_swap(replay_move, self)
+        });
+        self.settle();
+        self.effect_player.audio_effect_volume = volume;
+        self.effect_player.clear();
+        println!("Now at move {} of {}", position, playback.len());
+        if position == wanted {
+            self.playback = Some(playback);
+        }
+    }
+
+    /// Run the phases straight through until the board is waiting on input again
+    fn settle(&mut self) {
+        let mut cross_positions = Vec::new();
+        while !matches!(
+            self.phase,
+            Phase::TakingInput | Phase::LevelWon(_) | Phase::LevelFailed
+        ) {
+            phases::run_phase(None, self.clock.now(), self, &mut cross_positions);
+            self.tokens.values_mut().for_each(Token::skip_animation);
+        }
+    }
+
+    pub fn reset_idle(&mut self, now: f64) {
+        self.idle_since = now;
+        self.hint = None;
+    }
+
+    pub fn swap_tokens(&mut self, pos_a: &Position, pos_b: &Position) {
+        self.board.swap(pos_a, pos_b);
+        let mut token_a = self.tokens.remove(pos_a).unwrap_or_default();
+        let mut token_b = self.tokens.remove(pos_b).unwrap_or_default();
+        let animation_time = ANIMATION_TIME_PER_TILE;
+        let now = self.clock.now();
+        // Overshooting a little makes the swap snap into place
+        token_a.animate_move_to(
+            pos_a.clone(),
+            pos_b.clone(),
+            animation_time,
+            Easing::Back,
+            now,
+        );
+        token_b.animate_move_to(
+            pos_b.clone(),
+            pos_a.clone(),
+            animation_time,
+            Easing::Back,
+            now,
+        );
+        self.tokens.insert(pos_b.clone(), token_a);
+        self.tokens.insert(pos_a.clone(), token_b);
+    }
+
+    /// Swing the tokens at `pos_a` and `pos_b` part of the way toward each other and back, for a
+    /// swap that isn't allowed
+    pub fn animate_denied_swap(&mut self, pos_a: &Position, pos_b: &Position) {
+        let animation_time = DENIED_SWAP_ANIMATION_TIME;
+        let now = self.clock.now();
+        for (pos, toward) in [(pos_a, pos_b), (pos_b, pos_a)] {
+            if let Some(token) = self.tokens.get_mut(pos) {
+                token.animate_nudge(pos, toward, animation_time, now);
+            }
+        }
+    }
+
+    /// Score a board clear and mirror it onto the visual tokens, exploding everything that was
+    /// removed
+    pub fn apply_clear(&mut self, clear_result: &ClearResult) {
+        let now = self.clock.now();
+        let points = self.score.add_clear(clear_result);
+        self.objectives.record(clear_result);
+        println!("Scored {} (chain of {})", points, self.score.chain());
+        for (line, match_kind) in clear_result.matches.iter() {
+            println!(
+                "Removing tokens in {:?} matched group {:?}",
+                match_kind, line
+            );
+        }
+        for (pos, special) in clear_result.detonations.iter() {
+            println!("Detonating {:?} at {:?}", special, pos);
+        }
+        for (pos, _) in clear_result.removed.iter() {
+            self.tokens.remove(pos);
+            self.effect_player
+                .spawn_effect(pos.clone(), EffectKind::Explosion, now);
+        }
+        for pos in clear_result.created.iter() {
+            self.tokens.insert(pos.clone(), Token::appearing(now));
+        }
+        for pos in clear_result.collected.iter() {
+            println!("Collected ingredient at {:?}", pos);
+            self.tokens.remove(pos);
+        }
+        for (pos, blocker) in clear_result.damaged.iter() {
+            println!("{:?} at {:?} hit, {} left", blocker.kind, pos, blocker.hits);
+            self.effect_player.spawn_effect(
+                pos.clone(),
+                EffectKind::for_blocker(blocker.kind),
+                now,
+            );
+        }
+    }
+
+    /// Mirror a set of board moves onto the visual tokens, each falling into place and bouncing
+    /// as it lands
+    pub fn animate_moves(&mut self, moves: &[PieceMove]) {
+        self.animate_moves_with(moves, Easing::Bounce, |piece_move| {
+            piece_move.distance() as f64 * ANIMATION_TIME_PER_TILE
+        });
+    }
+
+    /// Like [`Self::animate_moves`] but with every token gliding over and arriving at once
+    pub fn animate_moves_over(&mut self, moves: &[PieceMove], animation_time: f64) {
+        self.animate_moves_with(moves, Easing::EaseInOut, |_| animation_time);
+    }
+
+    fn animate_moves_with(
+        &mut self,
+        moves: &[PieceMove],
+        easing: Easing,
+        animation_time: impl Fn(&PieceMove) -> f64,
+    ) {
+        let now = self.clock.now();
+        // Lift every moving token off first so moves landing on another's start don't collide
+        let moving = moves
+            .iter()
+            .map(|m| self.tokens.remove(&m.from).unwrap_or_default())
+            .collect::<Vec<_>>();
+        for (piece_move, mut token) in moves.iter().zip(moving) {
+            token.animate_move_to(
+                piece_move.from.clone(),
+                piece_move.to.clone(),
+                animation_time(piece_move),
+                easing,
+                now,
+            );
+            self.tokens.insert(piece_move.to.clone(), token);
+        }
+    }
+}
+
+/// Run `mode`'s clock for `dt` seconds if a move can be made in `phase`. Animations, cascades,
+/// reshuffles and the results screen all hold it, as the player can't do anything during them.
+fn tick_mode(mode: &mut Mode, phase: &Phase, playback: Option<&Playback>, dt: f64) {
+    if !matches!(phase, Phase::TakingInput) {
+        return;
+    }
+    let dt = match playback.and_then(Playback::next_move) {
+        Some(next) => dt.min(next.clock - mode.time_used()).max(0.0),
+        None => dt,
+    };
+    mode.tick(dt);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use matchup::mode::GameMode;
+
+    #[test]
+    fn test_mode_clock_held_while_input_blocked() {
+        let mut mode = Mode::new(GameMode::Timed(60.0));
+        let blocked = [
+            Phase::MovedAndAnimating(vec![Position::new(0, 0), Position::new(1, 0)]),
+            Phase::SpecialCombo(vec![Position::new(0, 0), Position::new(1, 0)]),
+            Phase::GravityRefill,
+            Phase::CheckWholeGrid,
+            Phase::Reshuffle,
+            Phase::SwapDenied,
+            Phase::Animating(Rc::new(Phase::TakingInput)),
+            Phase::LevelWon(3),
+            Phase::LevelFailed,
+        ];
+        for phase in blocked.iter() {
+            tick_mode(&mut mode, phase, None, 1.0);
+            assert_eq!(mode.time_used(), 0.0, "ran during {:?}", phase);
         }
+        tick_mode(&mut mode, &Phase::TakingInput, None, 1.0);
+        assert_eq!(mode.time_used(), 1.0);
     }
 }
@@ -0,0 +1,199 @@
+use crate::board::{Board, PieceMove};
+use crate::core::Position;
+use crate::moves::has_valid_move;
+use crate::piece::{Piece, TokenType};
+use crate::token_grid;
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+// Practically every random fill has a valid move, this is just to stop a pathological colour set
+// (e.g. a single colour) from looping forever
+const MAX_ATTEMPTS: usize = 1000;
+// A full board nearly always shuffles into something playable within a handful of tries, if it
+// hasn't by now it probably can't (e.g. too few of each colour left)
+const MAX_SHUFFLE_ATTEMPTS: usize = 100;
+
+/// Fill every empty cell of `template` randomly from its colours such that there are no matches
+/// already on the board and at least one valid move. The template's own pieces are kept where
+/// they are, and cells whose blocker can't hold a piece are left empty. Returns None if a
+/// thousand fills go by without one, which only happens when the template's colours and fixed
+/// pieces can't make a playable board.
+pub fn generate_board<R: Rng + ?Sized>(template: &Board, rng: &mut R) -> Option<Board> {
+    for _ in 0..MAX_ATTEMPTS {
+        let board = random_fill(template, rng);
+        if is_playable(&board) {
+            return Some(board);
+        }
+    }
+    None
+}
+
+/// No matches waiting to be cleared and at least one swap that makes one
+fn is_playable(board: &Board) -> bool {
+    token_grid::check_entire_grid(&board.pieces).is_empty() && has_valid_move(board)
+}
+
+/// Rearrange the tokens already on the board so there are no matches and at least one valid
+/// move, returning where each token moved. Returns None, leaving the board untouched, if no such
+/// arrangement was found.
+pub fn shuffle_board<R: Rng + ?Sized>(board: &mut Board, rng: &mut R) -> Option<Vec<PieceMove>> {
+    // Chained pieces stay where they are
+    let mut positions = board
+        .pieces
+        .keys()
+        .filter(|pos| !board.is_locked(pos))
+        .cloned()
+        .collect::<Vec<_>>();
+    // Sorted so the same seed always gives the same shuffle
+    positions.sort_by_key(|pos| (pos.y, pos.x));
+    let mut originals = positions
+        .iter()
+        .map(|pos| (pos.clone(), board.get(pos).unwrap().clone()))
+        .collect::<Vec<_>>();
+
+    for _ in 0..MAX_SHUFFLE_ATTEMPTS {
+        originals.shuffle(rng);
+        let mut shuffled = board.clone();
+        for (pos, (_, piece)) in positions.iter().zip(originals.iter()) {
+            shuffled.pieces.insert(pos.clone(), piece.clone());
+        }
+        if is_playable(&shuffled) {
+            *board = shuffled;
+            let moves = originals
+                .iter()
+                .zip(positions.iter())
+                .map(|((from, _), to)| PieceMove {
+                    from: from.clone(),
+                    to: to.clone(),
+                })
+                .collect();
+            return Some(moves);
+        }
+    }
+    None
+}
+
+/// Throw away the board and generate a fresh one, for when it can't be shuffled. Returns the
+/// new tokens dropping in from a board's height above, or None, leaving the board untouched, if
+/// no playable board could be generated.
+pub fn regenerate_board<R: Rng + ?Sized>(board: &mut Board, rng: &mut R) -> Option<Vec<PieceMove>> {
+    let mut template = board.clone();
+    template.pieces.clear();
+    *board = generate_board(&template, rng)?;
+    let height = board.shape.height as i32;
+    let moves = board
+        .pieces
+        .keys()
+        .map(|pos| PieceMove {
+            from: Position::new(pos.x, pos.y - height),
+            to: pos.clone(),
+        })
+        .collect();
+    Some(moves)
+}
+
+/// Fill in row order, never picking a colour that would complete a line with the tokens already
+/// placed around it, fixed starting tokens to the right and below included
+fn random_fill<R: Rng + ?Sized>(template: &Board, rng: &mut R) -> Board {
+    let mut board = template.clone();
+    let colours = &template.colours;
+    for Position { x, y } in template.shape.cells() {
+        let pos = Position::new(x, y);
+        if board.contains(&pos)
+            || board
+                .blockers
+                .get(&pos)
+                .is_some_and(|blocker| !blocker.holds_piece())
+        {
+            continue;
+        }
+        // How many tokens of `type_` run on from the cell in one direction
+        let run = |type_: TokenType, dx: i32, dy: i32| {
+            (1..)
+                .take_while(|i| {
+                    board
+                        .get(&Position::new(x + dx * i, y + dy * i))
+                        .is_some_and(|piece| piece.type_ == type_)
+                })
+                .count()
+        };
+        let makes_line = |type_: TokenType| {
+            run(type_, -1, 0) + run(type_, 1, 0) >= 2 || run(type_, 0, -1) + run(type_, 0, 1) >= 2
+        };
+        let allowed = colours
+            .iter()
+            .filter(|type_| !makes_line(**type_))
+            .cloned()
+            .collect::<Vec<_>>();
+        // With fewer than three colours a match can be unavoidable, fall back to anything
+        let type_ = *allowed.choose(rng).or_else(|| colours.choose(rng)).unwrap();
+        board.pieces.insert(pos, Piece::new(type_));
+    }
+    board
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rng::GameRng;
+    use crate::shape::BoardShape;
+    use crate::test_util::board_from_rows;
+    use hashbrown::HashMap;
+
+    #[test]
+    fn test_generated_board() {
+        for seed in 0..20 {
+            let template = Board::new(HashMap::new(), BoardShape::square(10));
+            let board = generate_board(&template, &mut GameRng::from_seed(seed)).unwrap();
+            assert_eq!(board.pieces.len(), 100);
+            assert!(token_grid::check_entire_grid(&board.pieces).is_empty());
+            assert!(has_valid_move(&board));
+
+            let again = generate_board(&template, &mut GameRng::from_seed(seed)).unwrap();
+            assert!(board
+                .pieces
+                .iter()
+                .all(|(pos, piece)| again.get(pos) == Some(piece)));
+        }
+
+        // No swap on a 2x2 board can line up three
+        let template = Board::new(HashMap::new(), BoardShape::square(2));
+        assert!(generate_board(&template, &mut GameRng::from_seed(0)).is_none());
+    }
+
+    #[test]
+    fn test_generated_masked_board() {
+        let shape = BoardShape::from_rows(&[".##.##.", "#######", "#######", ".#####.", "..###.."]);
+        let board = generate_board(
+            &Board::new(HashMap::new(), shape.clone()),
+            &mut GameRng::from_seed(3),
+        )
+        .unwrap();
+        assert_eq!(board.pieces.len(), shape.cells().len());
+        assert!(board.pieces.keys().all(|pos| shape.is_cell(pos)));
+        assert!(token_grid::check_entire_grid(&board.pieces).is_empty());
+        assert!(has_valid_move(&board));
+    }
+
+    #[test]
+    fn test_shuffle_dead_board() {
+        // No swap on this board makes a match
+        let mut board = board_from_rows(&["RGBR", "YPYP", "BRGB", "PYPY"]);
+        assert!(!has_valid_move(&board));
+
+        let count_colours = |board: &Board| {
+            let mut counts = TokenType::ALL_REGULAR
+                .iter()
+                .map(|t| board.pieces.values().filter(|p| p.type_ == *t).count())
+                .collect::<Vec<_>>();
+            counts.sort();
+            counts
+        };
+        let before = count_colours(&board);
+        let moves = shuffle_board(&mut board, &mut GameRng::from_seed(1)).unwrap();
+        assert_eq!(moves.len(), 16);
+        assert_eq!(count_colours(&board), before);
+        assert!(token_grid::check_entire_grid(&board.pieces).is_empty());
+        assert!(has_valid_move(&board));
+    }
+}
@@ -0,0 +1,162 @@
+use crate::board::Board;
+use crate::core::Position;
+use crate::mode::Mode;
+use crate::objective::Objectives;
+use crate::rng::GameRng;
+use crate::score::Score;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+pub const DEFAULT_HISTORY_DEPTH: usize = 20;
+
+/// Everything needed to put a game back exactly as it was, rng included so the same swap plays
+/// out the same way again
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub board: Board,
+    pub score: Score,
+    pub objectives: Objectives,
+    pub mode: Mode,
+    pub rng: GameRng,
+}
+
+/// A snapshot along with the swap that was made from it
+#[derive(Debug, Clone)]
+pub struct Step {
+    pub snapshot: Snapshot,
+    pub swap: (Position, Position),
+}
+
+/// Undo and redo stacks of committed moves
+#[derive(Debug, Clone)]
+pub struct History {
+    undo: VecDeque<Step>,
+    redo: Vec<Step>,
+    // Most moves that can be undone, 0 turns undo off
+    pub depth: usize,
+    // Whether undo is allowed in competitive modes, off unless asked for
+    pub in_competitive: bool,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new(DEFAULT_HISTORY_DEPTH)
+    }
+}
+
+impl History {
+    pub fn new(depth: usize) -> Self {
+        Self {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            depth,
+            in_competitive: false,
+        }
+    }
+
+    /// Whether undo can be used at all under `mode`
+    pub fn is_enabled(&self, mode: &Mode) -> bool {
+        self.depth > 0 && (self.in_competitive || !mode.kind.is_competitive())
+    }
+
+    /// Remember the state from just before `swap` was made. Making a new move forgets anything
+    /// that was undone.
+    pub fn record(&mut self, snapshot: Snapshot, swap: (Position, Position)) {
+        if self.depth == 0 {
+            return;
+        }
+        self.redo.clear();
+        self.undo.push_back(Step { snapshot, swap });
+        while self.undo.len() > self.depth {
+            self.undo.pop_front();
+        }
+    }
+
+    /// Step back one move from `current`, which becomes redoable. Returns the step to restore.
+    pub fn undo(&mut self, current: Snapshot) -> Option<Step> {
+        let step = self.undo.pop_back()?;
+        self.redo.push(Step {
+            snapshot: current,
+            swap: step.swap.clone(),
+        });
+        Some(step)
+    }
+
+    /// Replay the last undone move from `current`. Returns the step to restore.
+    pub fn redo(&mut self, current: Snapshot) -> Option<Step> {
+        let step = self.redo.pop()?;
+        self.undo.push_back(Step {
+            snapshot: current,
+            swap: step.swap.clone(),
+        });
+        Some(step)
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::level::Level;
+    use crate::mode::GameMode;
+    use crate::moves::find_valid_moves;
+
+    fn snapshot(board: &Board, rng: &GameRng) -> Snapshot {
+        Snapshot {
+            board: board.clone(),
+            score: Score::new(),
+            objectives: Objectives::default(),
+            mode: Mode::new(GameMode::Endless),
+            rng: rng.clone(),
+        }
+    }
+
+    #[test]
+    fn test_undo_redo() {
+        let mut rng = GameRng::from_seed(7);
+        let mut board = Level::classic().build_board(&mut rng).unwrap();
+        let mut history = History::new(1);
+
+        let valid_move = find_valid_moves(&board).remove(0);
+        let swap = (valid_move.from.clone(), valid_move.to.clone());
+        history.record(snapshot(&board, &rng), swap.clone());
+        board.play_move(&swap.0, &swap.1, &mut rng);
+        let after = board.pieces.clone();
+
+        let step = history.undo(snapshot(&board, &rng)).unwrap();
+        assert_eq!(step.swap, swap);
+        assert!(!history.can_undo());
+
+        // Playing the same move again from the restored state gives the same board
+        let mut replayed = step.snapshot.board.clone();
+        replayed.play_move(&swap.0, &swap.1, &mut step.snapshot.rng.clone());
+        assert_eq!(replayed.pieces, after);
+
+        let redone = history.redo(step.snapshot).unwrap();
+        assert_eq!(redone.snapshot.board.pieces, after);
+        assert!(history.can_undo() && !history.can_redo());
+
+        // Only `depth` moves are kept
+        history.record(snapshot(&board, &rng), swap.clone());
+        history.record(snapshot(&board, &rng), swap);
+        assert!(history.undo(snapshot(&board, &rng)).is_some());
+        assert!(history.undo(snapshot(&board, &rng)).is_none());
+
+        assert!(!History::new(0).is_enabled(&Mode::new(GameMode::Endless)));
+        assert!(!history.is_enabled(&Mode::new(GameMode::Timed(60.0))));
+        history.in_competitive = true;
+        assert!(history.is_enabled(&Mode::new(GameMode::Timed(60.0))));
+    }
+}
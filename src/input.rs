@@ -0,0 +1,193 @@
+//! Turning presses and drags of the pointer into swaps. A press both selects a token and picks it
+//! up, so a swap can be made either by clicking two neighbours in turn or by dragging a token onto
+//! a neighbour.
+use crate::core::Position;
+
+// How far of the way onto a neighbour a token has to be dragged before it's swapped with it
+pub const DRAG_THRESHOLD: f32 = 0.5;
+
+fn tile_size() -> f32 {
+    Position::new(1, 0).to_world().0
+}
+
+/// A token held down by the pointer, which is swapped with whichever neighbour it's dragged onto
+#[derive(Debug, Clone, PartialEq)]
+pub struct Drag {
+    pub from: Position,
+    // World positions the pointer was pressed at and is at now
+    start: (f32, f32),
+    current: (f32, f32),
+}
+
+impl Drag {
+    pub fn new(from: Position, start: (f32, f32)) -> Self {
+        Self {
+            from,
+            start,
+            current: start,
+        }
+    }
+
+    /// How far the pointer has been dragged along whichever of across or down it's gone further,
+    /// the other left at 0
+    fn along_axis(&self) -> (f32, f32) {
+        let (dx, dy) = (self.current.0 - self.start.0, self.current.1 - self.start.1);
+        if dx.abs() >= dy.abs() {
+            (dx, 0.0)
+        } else {
+            (0.0, dy)
+        }
+    }
+
+    /// Follow the pointer to world position `to`, returning the neighbour to swap with once it's
+    /// been dragged past [`DRAG_THRESHOLD`]
+    pub fn move_to(&mut self, to: (f32, f32)) -> Option<Position> {
+        self.current = to;
+        let (dx, dy) = self.along_axis();
+        if dx.abs().max(dy.abs()) < DRAG_THRESHOLD * tile_size() {
+            return None;
+        }
+        let (step_x, step_y) = if dx != 0.0 {
+            (dx.signum() as i32, 0)
+        } else {
+            (0, dy.signum() as i32)
+        };
+        Some(Position::new(self.from.x + step_x, self.from.y + step_y))
+    }
+
+    /// How far to draw the held token from its place so it follows the pointer, never further
+    /// than it has to go to be swapped
+    pub fn offset(&self) -> (f32, f32) {
+        let limit = DRAG_THRESHOLD * tile_size();
+        let (dx, dy) = self.along_axis();
+        (dx.clamp(-limit, limit), dy.clamp(-limit, limit))
+    }
+}
+
+/// The token the player has selected by clicking, and the one they're holding down, if any
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Selection {
+    pub selected: Option<Position>,
+    pub drag: Option<Drag>,
+    // Pressed while it was already selected, so letting go without dragging it deselects it
+    deselect_on_release: bool,
+}
+
+impl Selection {
+    /// The pointer went down on board position `pos`, at world position `at`. Returns the swap to
+    /// make if a neighbour of the selected token was pressed, otherwise `pos` is selected and
+    /// picked up.
+    pub fn press(&mut self, pos: Position, at: (f32, f32)) -> Option<(Position, Position)> {
+        self.deselect_on_release = false;
+        match self.selected.take() {
+            Some(selected) if selected.is_adjacent(&pos) => {
+                self.drag = None;
+                return Some((selected, pos));
+            }
+            Some(selected) => self.deselect_on_release = selected == pos,
+            None => {}
+        }
+        self.selected = Some(pos.clone());
+        self.drag = Some(Drag::new(pos, at));
+        None
+    }
+
+    /// The pointer moved to world position `at` while down. Returns the swap to make once the
+    /// held token has been dragged far enough onto a neighbour, letting go of it.
+    pub fn drag_to(&mut self, at: (f32, f32)) -> Option<(Position, Position)> {
+        let to = self.drag.as_mut()?.move_to(at)?;
+        let from = self.drag.take()?.from;
+        self.selected = None;
+        self.deselect_on_release = false;
+        Some((from, to))
+    }
+
+    /// The pointer was let go of, or the board stopped taking input
+    pub fn release(&mut self) {
+        if self.drag.take().is_some() && self.deselect_on_release {
+            self.selected = None;
+        }
+        self.deselect_on_release = false;
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_selection() {
+        let (a, b, far) = (
+            Position::new(1, 1),
+            Position::new(2, 1),
+            Position::new(3, 3),
+        );
+        let at = |pos: &Position| {
+            let (x, y) = pos.to_world();
+            let half = tile_size() / 2.0;
+            (x + half, y + half)
+        };
+
+        // Clicking two neighbours in turn swaps them
+        let mut selection = Selection::default();
+        assert_eq!(selection.press(a.clone(), at(&a)), None);
+        selection.release();
+        assert_eq!(selection.selected, Some(a.clone()));
+        assert_eq!(
+            selection.press(b.clone(), at(&b)),
+            Some((a.clone(), b.clone()))
+        );
+        assert_eq!(selection, Selection::default());
+
+        // Clicking the selected token again puts it down, but dragging it still swaps
+        assert_eq!(selection.press(a.clone(), at(&a)), None);
+        selection.release();
+        assert_eq!(selection.press(a.clone(), at(&a)), None);
+        assert_eq!(selection.selected, Some(a.clone()));
+        selection.release();
+        assert_eq!(selection.selected, None);
+        selection.press(a.clone(), at(&a));
+        selection.release();
+        assert_eq!(selection.press(a.clone(), at(&a)), None);
+        assert_eq!(selection.drag_to(at(&b)), Some((a.clone(), b.clone())));
+        assert_eq!(selection, Selection::default());
+
+        // Pressing a token away from the selected one selects and picks up that one instead
+        selection.press(a.clone(), at(&a));
+        selection.release();
+        assert_eq!(selection.press(far.clone(), at(&far)), None);
+        assert_eq!(selection.selected, Some(far.clone()));
+        assert_eq!(selection.drag.as_ref().map(|drag| &drag.from), Some(&far));
+        selection.release();
+        assert_eq!(selection.selected, Some(far.clone()));
+    }
+
+    #[test]
+    fn test_drag() {
+        let from = Position::new(1, 1);
+        let start = from.to_world();
+        let tile = tile_size();
+        let mut drag = Drag::new(from.clone(), start);
+
+        // Short of the threshold the token follows the pointer along the axis it moved most on
+        assert_eq!(
+            drag.move_to((start.0 + tile * 0.1, start.1 - tile * 0.3)),
+            None
+        );
+        assert_eq!(drag.offset(), (0.0, -tile * 0.3));
+        // Past it the neighbour that way is swapped with, and the token is drawn no further
+        assert_eq!(
+            drag.move_to((start.0, start.1 - tile)),
+            Some(Position::new(1, 0))
+        );
+        assert_eq!(drag.offset(), (0.0, -tile * DRAG_THRESHOLD));
+        assert_eq!(
+            drag.move_to((start.0 + tile * 0.6, start.1)),
+            Some(Position::new(2, 1))
+        );
+    }
+}
@@ -0,0 +1,450 @@
+//! Levels are written in RON, one per file, e.g.
+//!
+//! ```text
+//! Level(
+//!     name: "Frozen Heart",
+//!     width: 7,
+//!     height: 6,
+//!     // `.` is a hole, `#` a random token, R, G, B, Y or P a fixed starting token and I an
+//!     // ingredient
+//!     layout: [
+//!         ".##.##.",
+//!         "#######",
+//!         "###R###",
+//!         ".##R##.",
+//!         "..###..",
+//!         "...#...",
+//!     ],
+//!     blockers: [
+//!         (x: 3, y: 1, kind: Ice, hits: 2),
+//!         (x: 3, y: 4, kind: Stone, hits: 1),
+//!     ],
+//!     colours: [Red, Green, Blue, Yellow],
+//!     moves: Some(20),
+//!     objectives: [Score(3000), ClearIce],
+//!     // Scores for a second and third star, winning at all earns the first
+//!     star_scores: [4000, 6000],
+//! )
+//! ```
+//!
+//! Everything but `name`, `width` and `height` can be left out: no layout is a full rectangle of
+//! random tokens, no colours is all of them, no moves is unlimited and no objectives is endless.
+use crate::blocker::{Blocker, BlockerKind};
+use crate::board::Board;
+use crate::core::Position;
+use crate::generator::generate_board;
+use crate::objective::{Objective, Objectives};
+use crate::piece::{Piece, TokenType};
+use crate::rng::GameRng;
+use crate::shape::BoardShape;
+use crate::token_grid;
+use hashbrown::{HashMap, HashSet};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Where levels are loaded from at startup
+pub const LEVELS_DIR: &str = "res/levels";
+pub const LEVEL_EXTENSION: &str = "ron";
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct BlockerPlacement {
+    pub x: i32,
+    pub y: i32,
+    pub kind: BlockerKind,
+    #[serde(default = "default_hits")]
+    pub hits: u32,
+}
+
+fn default_hits() -> u32 {
+    1
+}
+
+fn default_colours() -> Vec<TokenType> {
+    TokenType::ALL_REGULAR.to_vec()
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Level {
+    pub name: String,
+    pub width: usize,
+    pub height: usize,
+    #[serde(default)]
+    pub layout: Vec<String>,
+    #[serde(default)]
+    pub blockers: Vec<BlockerPlacement>,
+    #[serde(default = "default_colours")]
+    pub colours: Vec<TokenType>,
+    #[serde(default)]
+    pub moves: Option<u32>,
+    #[serde(default)]
+    pub objectives: Vec<Objective>,
+    #[serde(default)]
+    pub star_scores: Vec<u64>,
+}
+
+#[derive(Debug)]
+pub enum LevelError {
+    Io(std::io::Error),
+    Parse(ron::error::SpannedError),
+    // Every problem found with an otherwise well-formed level
+    Invalid(Vec<String>),
+}
+
+impl fmt::Display for LevelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "couldn't read level: {}", err),
+            Self::Parse(err) => write!(f, "malformed level at {}", err),
+            Self::Invalid(problems) => {
+                write!(f, "invalid level:")?;
+                for problem in problems {
+                    write!(f, "\n  - {}", problem)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for LevelError {}
+
+impl Level {
+    /// The original endless 10x10 board with every colour
+    pub fn classic() -> Self {
+        Self {
+            name: "Classic".to_owned(),
+            width: 10,
+            height: 10,
+            layout: Vec::new(),
+            blockers: Vec::new(),
+            colours: default_colours(),
+            moves: None,
+            objectives: Vec::new(),
+            star_scores: Vec::new(),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, LevelError> {
+        let text = std::fs::read_to_string(path).map_err(LevelError::Io)?;
+        Self::from_ron(&text)
+    }
+
+    /// Parse and validate a level
+    pub fn from_ron(text: &str) -> Result<Self, LevelError> {
+        let level: Level = ron::from_str(text).map_err(LevelError::Parse)?;
+        level.validate()?;
+        Ok(level)
+    }
+
+    /// Check the level makes sense, reporting everything wrong with it rather than just the
+    /// first problem
+    pub fn validate(&self) -> Result<(), LevelError> {
+        let mut problems = Vec::new();
+
+        if self.width == 0 || self.height == 0 {
+            problems.push(format!(
+                "board is {}x{}, it needs at least one cell",
+                self.width, self.height
+            ));
+        }
+        if !self.layout.is_empty() {
+            if self.layout.len() != self.height {
+                problems.push(format!(
+                    "layout has {} rows but height is {}",
+                    self.layout.len(),
+                    self.height
+                ));
+            }
+            for (y, row) in self.layout.iter().enumerate() {
+                if row.chars().count() != self.width {
+                    problems.push(format!(
+                        "layout row {} is {} wide but width is {}",
+                        y,
+                        row.chars().count(),
+                        self.width
+                    ));
+                }
+                for (x, c) in row.chars().enumerate() {
+                    if !matches!(c, '.' | '#' | 'I') && layout_colour(c).is_none() {
+                        problems.push(format!(
+                            "unknown character '{}' in layout at ({}, {}), expected '.', '#', 'I' \
+                             or one of R G B Y P",
+                            c, x, y
+                        ));
+                    }
+                }
+            }
+        }
+
+        let shape = self.shape();
+        let fixed = self.fixed_pieces();
+        let mut placed = HashSet::new();
+        for blocker in self.blockers.iter() {
+            let pos = Position::new(blocker.x, blocker.y);
+            if !shape.is_cell(&pos) {
+                problems.push(format!(
+                    "{:?} at ({}, {}) isn't on a cell of the board",
+                    blocker.kind, pos.x, pos.y
+                ));
+            }
+            if !placed.insert(pos.clone()) {
+                problems.push(format!("more than one blocker at ({}, {})", pos.x, pos.y));
+            }
+            if blocker.hits == 0 {
+                problems.push(format!(
+                    "{:?} at ({}, {}) needs at least one hit",
+                    blocker.kind, pos.x, pos.y
+                ));
+            }
+            if blocker.kind == BlockerKind::Stone && fixed.contains_key(&pos) {
+                problems.push(format!(
+                    "Stone at ({}, {}) is on a starting token, stones can't hold one",
+                    pos.x, pos.y
+                ));
+            }
+        }
+
+        let mut seen_colours = HashSet::new();
+        for colour in self.colours.iter() {
+            if !colour.is_regular() {
+                problems.push(format!(
+                    "{:?} isn't a colour a board can be filled with",
+                    colour
+                ));
+            }
+            if !seen_colours.insert(*colour) {
+                problems.push(format!("{:?} is listed in colours more than once", colour));
+            }
+        }
+        if seen_colours.len() < 3 {
+            problems.push(format!(
+                "needs at least 3 colours to fill a board without matches, has {}",
+                seen_colours.len()
+            ));
+        }
+
+        let starting_matches = token_grid::check_entire_grid(&fixed);
+        for (group, _) in starting_matches.iter() {
+            let mut group = group.clone();
+            group.sort_by_key(|pos| (pos.y, pos.x));
+            problems.push(format!(
+                "starting tokens already match at {}",
+                group
+                    .iter()
+                    .map(|pos| format!("({}, {})", pos.x, pos.y))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+
+        if self.moves == Some(0) {
+            problems.push("moves must be at least 1, leave it out for unlimited".to_owned());
+        }
+        for objective in self.objectives.iter() {
+            match objective {
+                Objective::Score(0) => {
+                    problems.push("Score objective of 0 is already complete".to_owned())
+                }
+                Objective::Collect(colour, _) if !self.colours.contains(colour) => {
+                    problems.push(format!(
+                        "Collect objective wants {:?} but it isn't one of the level's colours",
+                        colour
+                    ))
+                }
+                Objective::Collect(_, 0) => {
+                    problems.push("Collect objective of 0 is already complete".to_owned())
+                }
+                Objective::Ingredients(0) => {
+                    problems.push("Ingredients objective of 0 is already complete".to_owned())
+                }
+                Objective::ClearIce
+                    if !self
+                        .blockers
+                        .iter()
+                        .any(|blocker| blocker.kind == BlockerKind::Ice) =>
+                {
+                    problems.push("ClearIce objective but the level has no ice".to_owned())
+                }
+                _ => {}
+            }
+        }
+
+        if self.star_scores.len() > 2 {
+            problems.push(format!(
+                "star_scores has {} entries, only the second and third stars need one",
+                self.star_scores.len()
+            ));
+        }
+        if self.star_scores.windows(2).any(|pair| pair[0] >= pair[1]) {
+            problems.push("star_scores must go up".to_owned());
+        }
+
+        // Only worth trying once everything the board is built from is known to be sound
+        if problems.is_empty() && self.build_board(&mut GameRng::from_seed(0)).is_none() {
+            problems.push("no playable starting board can be generated".to_owned());
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(LevelError::Invalid(problems))
+        }
+    }
+
+    pub fn shape(&self) -> BoardShape {
+        if self.layout.is_empty() {
+            BoardShape::rectangle(self.width, self.height)
+        } else {
+            let rows = self.layout.iter().map(String::as_str).collect::<Vec<_>>();
+            let mut shape = BoardShape::from_rows(&rows);
+            // Rows are checked against these in `validate`, but keep the shape to size regardless
+            shape.width = self.width;
+            shape.height = self.height;
+            shape
+        }
+    }
+
+    fn fixed_pieces(&self) -> HashMap<Position, Piece> {
+        let mut pieces = HashMap::new();
+        for (y, row) in self.layout.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                let type_ = if c == 'I' {
+                    Some(TokenType::Ingredient)
+                } else {
+                    layout_colour(c)
+                };
+                if let Some(type_) = type_ {
+                    pieces.insert(Position::new(x as i32, y as i32), Piece::new(type_));
+                }
+            }
+        }
+        pieces
+    }
+
+    /// The board before its random tokens are filled in: shape, blockers, colours and any fixed
+    /// starting tokens
+    pub fn template(&self) -> Board {
+        let mut board = Board::new(self.fixed_pieces(), self.shape());
+        board.blockers = self
+            .blockers
+            .iter()
+            .map(|blocker| {
+                (
+                    Position::new(blocker.x, blocker.y),
+                    Blocker::new(blocker.kind, blocker.hits),
+                )
+            })
+            .collect();
+        board.colours = self.colours.clone();
+        let placed = board.pieces.values().filter(|p| p.is_ingredient()).count() as u32;
+        board.ingredients_to_spawn = Objectives::new(self.objectives.clone())
+            .ingredients_needed()
+            .saturating_sub(placed);
+        board
+    }
+
+    /// A ready to play board for the level, or None if no playable board could be generated,
+    /// which [`Level::validate`] checks for
+    pub fn build_board<R: Rng + ?Sized>(&self, rng: &mut R) -> Option<Board> {
+        generate_board(&self.template(), rng)
+    }
+}
+
+fn layout_colour(c: char) -> Option<TokenType> {
+    match c {
+        'R' => Some(TokenType::Red),
+        'G' => Some(TokenType::Green),
+        'B' => Some(TokenType::Blue),
+        'Y' => Some(TokenType::Yellow),
+        'P' => Some(TokenType::Purple),
+        _ => None,
+    }
+}
+
+/// Load every level in `dir`, in file name order. Levels that fail to load are returned with
+/// their error rather than dropped, so they can be reported.
+pub fn load_levels(dir: &Path) -> Vec<(PathBuf, Result<Level, LevelError>)> {
+    let mut paths = match std::fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == LEVEL_EXTENSION))
+            .collect::<Vec<_>>(),
+        Err(err) => return vec![(dir.to_path_buf(), Err(LevelError::Io(err)))],
+    };
+    paths.sort();
+    paths
+        .into_iter()
+        .map(|path| {
+            let level = Level::load(&path);
+            (path, level)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::moves::has_valid_move;
+
+    #[test]
+    fn test_bundled_levels() {
+        let levels = load_levels(Path::new(LEVELS_DIR));
+        assert!(!levels.is_empty());
+        for (path, level) in levels {
+            let level = level.unwrap_or_else(|err| panic!("{}: {}", path.display(), err));
+            let shape = level.shape();
+            for seed in 0..200 {
+                let board = level
+                    .build_board(&mut GameRng::from_seed(seed))
+                    .unwrap_or_else(|| {
+                        panic!("{} has no board with seed {}", path.display(), seed)
+                    });
+                assert!(board.pieces.keys().all(|pos| shape.is_cell(pos)));
+                assert!(
+                    token_grid::check_entire_grid(&board.pieces).is_empty(),
+                    "{} starts with a match with seed {}",
+                    path.display(),
+                    seed
+                );
+                assert!(
+                    has_valid_move(&board),
+                    "{} has no moves with seed {}",
+                    path.display(),
+                    seed
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_invalid_level() {
+        let Err(LevelError::Parse(_)) = Level::from_ron("Level(name: \"Oops\", width: 3") else {
+            panic!("expected a parse error");
+        };
+
+        let text = r##"Level(
+            name: "Broken",
+            width: 4,
+            height: 2,
+            layout: ["RRR#", "#x#"],
+            blockers: [(x: 5, y: 0, kind: Ice)],
+            colours: [Red, Green],
+            objectives: [ClearIce],
+        )"##;
+        let Err(LevelError::Invalid(problems)) = Level::from_ron(text) else {
+            panic!("expected an invalid level");
+        };
+        assert_eq!(
+            problems,
+            vec![
+                "layout row 1 is 3 wide but width is 4",
+                "unknown character 'x' in layout at (1, 1), expected '.', '#', 'I' or one of R G B Y P",
+                "Ice at (5, 0) isn't on a cell of the board",
+                "needs at least 3 colours to fill a board without matches, has 2",
+                "starting tokens already match at (0, 0), (1, 0), (2, 0)",
+            ]
+        );
+    }
+}
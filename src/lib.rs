@@ -0,0 +1,27 @@
+//! Headless match-3 engine. Everything in here is pure game logic and can be run without a
+//! window; the macroquad binary is a renderer and input adapter on top of it.
+pub mod bitboard;
+pub mod blocker;
+pub mod board;
+pub mod clock;
+pub mod core;
+pub mod generator;
+pub mod history;
+pub mod input;
+pub mod level;
+pub mod mode;
+pub mod moves;
+pub mod objective;
+pub mod piece;
+pub mod replay;
+pub mod rng;
+pub mod save;
+pub mod score;
+pub mod shape;
+pub mod simulation;
+pub mod solver;
+pub mod special;
+#[cfg(test)]
+mod test_util;
+pub mod token_grid;
+pub mod tween;
@@ -1,23 +1,34 @@
 mod animated_item;
-mod core;
 mod effect_player;
 mod game_state;
 mod phases;
+mod pointer;
 mod shaders;
 mod token;
-mod token_grid;
 
-use crate::animated_item::{AnimatedItem, AnimationScheme};
-use crate::core::{Position, TextureAtlas};
 use crate::effect_player::EffectPlayer;
 use crate::game_state::GameState;
-use crate::token::{is_valid_swap, swap_tokens, Modifier, Token, TokenType};
-use crate::token_grid::check_for_matches;
+use crate::pointer::Pointer;
+use crate::token::{DrawContext, Modifier};
 use futures::future::join_all;
 use hashbrown::HashMap;
-use macroquad::miniquad::{BlendFactor, BlendState, BlendValue, Equation};
 use macroquad::prelude::*;
-use phases::Phase;
+use matchup::blocker::{Blocker, BlockerKind};
+use matchup::clock::GameClock;
+use matchup::core::Position;
+use matchup::input::Drag;
+use matchup::level::{self, Level};
+use matchup::mode::{GameMode, Mode, Outcome};
+use matchup::piece::TokenType;
+use matchup::replay::{Playback, Replay, PLAYBACK_SPEEDS, REPLAYS_DIR, REPLAY_EXTENSION};
+use matchup::rng::GameRng;
+use matchup::save::{SaveError, SaveGame, SAVE_PATH};
+use matchup::solver::{Bot, Strategy};
+use phases::{Phase, PlayerInput};
+use std::path::{Path, PathBuf};
+
+// Game seconds a paused game moves on by each time it's stepped
+const FRAME_STEP: f64 = 1.0 / 60.0;
 
 fn window_conf() -> Conf {
     Conf {
@@ -64,16 +75,11 @@ async fn shader_toy() {
     let mut mat_index = 0;
     let file_shader_index = materials
         .iter()
-        .position(|m| match m {
-            Some(("FILE", _)) => true,
-            _ => false,
-        })
+        .position(|m| matches!(m, Some(("FILE", _))))
         .unwrap();
 
-    for m in materials.iter() {
-        if let Some((name, mat)) = m {
-            mat.set_uniform("test_color", vec4(1.5, 1.5, 1.5, 1.));
-        }
+    for (_, mat) in materials.iter().flatten() {
+        mat.set_uniform("test_color", vec4(1.5, 1.5, 1.5, 1.));
     }
 
     let mut camera =
@@ -101,7 +107,7 @@ async fn shader_toy() {
 
         set_material(&materials, mat_index);
         for texture in token_textures.iter() {
-            draw_texture(texture.clone(), x, y, WHITE);
+            draw_texture(*texture, x, y, WHITE);
             y += 40.0;
         }
 
@@ -132,13 +138,259 @@ fn get_material_name<'a>(materials: &'a [Option<(&str, Material)>], idx: usize)
 }
 
 fn set_material(materials: &[Option<(&str, Material)>], idx: usize) {
-    if let Some((name, mat)) = materials[idx] {
+    if let Some((_, mat)) = materials[idx] {
         gl_use_material(mat);
     } else {
         gl_use_default_material();
     }
 }
 
+fn draw_blocker(pos: &Position, blocker: &Blocker) {
+    let (x, y) = pos.to_world();
+    let (x2, y2) = Position::new(pos.x + 1, pos.y + 1).to_world();
+    let (w, h) = (x2 - x, y2 - y);
+    match blocker.kind {
+        BlockerKind::Ice => draw_rectangle(x, y, w, h, Color::new(0.7, 0.9, 1.0, 0.5)),
+        BlockerKind::Stone => {
+            draw_rectangle(x + 1.0, y + 1.0, w - 2.0, h - 2.0, GRAY);
+            draw_rectangle_lines(x + 1.0, y + 1.0, w - 2.0, h - 2.0, 2.0, DARKGRAY);
+        }
+        BlockerKind::Chain => {
+            let colour = Color::new(0.9, 0.8, 0.4, 0.9);
+            draw_line(x, y, x + w, y + h, 3.0, colour);
+            draw_line(x + w, y, x, y + h, 3.0, colour);
+        }
+    }
+    if blocker.hits > 1 {
+        draw_text(
+            &blocker.hits.to_string(),
+            x + w - 8.0,
+            y + h - 2.0,
+            12.0,
+            WHITE,
+        );
+    }
+}
+
+/// Results screen over the board
+fn draw_results(game_state: &GameState, outcome: Outcome, stars: u32) {
+    let shape = &game_state.board.shape;
+    let (width, height) = Position::new(shape.width as i32, shape.height as i32).to_world();
+    draw_rectangle(0.0, 0.0, width, height, Color::new(0.0, 0.0, 0.0, 0.7));
+
+    let centre_x = width / 2.0;
+    let title = game_state.mode.results_title(outcome);
+    let title_size = measure_text(title, None, 20, 1.0);
+    draw_text(
+        title,
+        centre_x - title_size.width / 2.0,
+        height * 0.3,
+        20.0,
+        WHITE,
+    );
+    if outcome == Outcome::Won {
+        for i in 0..3 {
+            let colour = if i < stars { GOLD } else { DARKGRAY };
+            draw_star(
+                centre_x + (i as f32 - 1.0) * 30.0,
+                height * 0.45,
+                12.0,
+                colour,
+            );
+        }
+    }
+    for (i, line) in [
+        format!("Score: {}", game_state.score.total),
+        "Click to play again".to_owned(),
+    ]
+    .iter()
+    .enumerate()
+    {
+        let size = measure_text(line, None, 12, 1.0);
+        draw_text(
+            line,
+            centre_x - size.width / 2.0,
+            height * 0.6 + 14.0 * i as f32,
+            12.0,
+            WHITE,
+        );
+    }
+}
+
+/// Five pointed star centred on (x, y)
+fn draw_star(x: f32, y: f32, radius: f32, colour: Color) {
+    let point = |i: usize, r: f32| {
+        let angle = std::f32::consts::PI * (i as f32 / 5.0 - 0.5);
+        Vec2::new(x + angle.cos() * r, y + angle.sin() * r)
+    };
+    // Alternate between the tips and the inner corners
+    let r = |i: usize| {
+        if i % 2 == 0 {
+            radius
+        } else {
+            radius * 0.45
+        }
+    };
+    let centre = Vec2::new(x, y);
+    for i in 0..10 {
+        draw_triangle(centre, point(i, r(i)), point(i + 1, r(i + 1)), colour);
+    }
+}
+
+/// Scan the levels directory, reporting any that fail to load, and pick the one named `wanted`
+/// (by file name or level name) or else the first. Falls back to the classic board if there are
+/// no usable levels at all.
+fn choose_level(wanted: Option<String>) -> Level {
+    let mut levels = Vec::new();
+    for (path, result) in level::load_levels(std::path::Path::new(level::LEVELS_DIR)) {
+        match result {
+            Ok(level) => levels.push((path, level)),
+            Err(err) => println!("Skipping {}: {}", path.display(), err),
+        }
+    }
+    println!(
+        "Found levels: {:?}",
+        levels
+            .iter()
+            .map(|(_, level)| &level.name)
+            .collect::<Vec<_>>()
+    );
+
+    let chosen = match wanted {
+        Some(wanted) => {
+            let found = levels.iter().position(|(path, level)| {
+                level.name.eq_ignore_ascii_case(&wanted)
+                    || path.file_stem().is_some_and(|stem| stem == wanted.as_str())
+            });
+            if found.is_none() {
+                println!("No level called {}", wanted);
+            }
+            found
+        }
+        None => None,
+    };
+    match chosen.or((!levels.is_empty()).then_some(0)) {
+        Some(idx) => levels.swap_remove(idx).1,
+        None => Level::classic(),
+    }
+}
+
+/// Keys for watching a replay: space pauses, 1, 2 and 4 set the speed, left and right step a
+/// move back or forward and home goes back to the start. Pausing and speed are the game clock's.
+fn replay_controls(game_state: &mut GameState) {
+    let Some(position) = game_state.playback.as_ref().map(Playback::position) else {
+        return;
+    };
+    let clock = &mut game_state.clock;
+    if is_key_pressed(KeyCode::Space) {
+        clock.set_paused(!clock.is_paused());
+    }
+    for (key, speed) in [KeyCode::Key1, KeyCode::Key2, KeyCode::Key4]
+        .into_iter()
+        .zip(PLAYBACK_SPEEDS)
+    {
+        if is_key_pressed(key) {
+            clock.set_scale(speed);
+        }
+    }
+    if is_key_pressed(KeyCode::Left) {
+        game_state.seek(position.saturating_sub(1));
+    } else if is_key_pressed(KeyCode::Right) {
+        game_state.seek(position + 1);
+    } else if is_key_pressed(KeyCode::Home) {
+        game_state.seek(0);
+    }
+}
+
+/// Keys for the game clock: P pauses, full stop steps a paused game on by a frame, and minus and
+/// equals halve or double how fast everything runs if `can_scale`. Returns the game time passing
+/// this frame.
+fn clock_controls(clock: &mut GameClock, can_scale: bool) -> f64 {
+    if is_key_pressed(KeyCode::P) {
+        clock.set_paused(!clock.is_paused());
+    }
+    if can_scale {
+        if is_key_pressed(KeyCode::Minus) {
+            clock.set_scale(clock.scale() / 2.0);
+        } else if is_key_pressed(KeyCode::Equal) {
+            clock.set_scale(clock.scale() * 2.0);
+        }
+    }
+    if clock.is_paused() && is_key_pressed(KeyCode::Period) {
+        return clock.step(FRAME_STEP);
+    }
+    clock.advance(get_frame_time() as f64)
+}
+
+/// The saved game to carry on with, unless the command line asks for a different one
+fn resume_game() -> Option<SaveGame> {
+    let fresh = ["--new", "--replay", "--level", "--seed", "--mode"]
+        .iter()
+        .any(|flag| has_flag(flag));
+    if fresh {
+        return None;
+    }
+    match SaveGame::load(Path::new(SAVE_PATH)) {
+        Ok(save) => Some(save),
+        Err(SaveError::Io(err)) if err.kind() == std::io::ErrorKind::NotFound => None,
+        Err(err) => {
+            println!("Couldn't resume the saved game: {}", err);
+            None
+        }
+    }
+}
+
+/// A new game as set up on the command line, or a replay to watch
+fn start_game(effect_player: EffectPlayer) -> Option<GameState> {
+    // A replay brings its own level, mode and seed
+    let replay = arg_value::<String>("--replay").and_then(|path| {
+        Replay::load(Path::new(&path))
+            .map_err(|err| println!("Couldn't load replay {}: {}", path, err))
+            .ok()
+    });
+    let (level, mode, rng) = match replay {
+        Some(ref replay) => {
+            println!("Watching a replay of {} moves", replay.moves.len());
+            (replay.level.clone(), replay.mode, replay.rng())
+        }
+        None => {
+            let rng = match arg_value("--seed") {
+                Some(seed) => GameRng::from_seed(seed),
+                None => GameRng::from_entropy(),
+            };
+            let level = choose_level(arg_value::<String>("--level"));
+            let mode = match arg_value::<String>("--mode") {
+                Some(name) => GameMode::from_name(&name, &level).unwrap_or_else(|| {
+                    println!(
+                        "Unknown mode {}, expected moves, timed, endless or zen",
+                        name
+                    );
+                    GameMode::for_level(&level)
+                }),
+                None => GameMode::for_level(&level),
+            };
+            (level, mode, rng)
+        }
+    };
+    println!("Playing with seed {}", rng.seed());
+    println!("Playing level {}", level.name);
+    println!("Mode: {:?}", mode);
+    let mut game_state = GameState::from_level(level, Mode::new(mode), effect_player, rng)?;
+    game_state.playback = replay.map(Playback::new);
+    Some(game_state)
+}
+
+fn has_flag(name: &str) -> bool {
+    std::env::args().any(|arg| arg == name)
+}
+
+/// Value following `name` on the command line, e.g. `--seed <n>` to replay a specific game
+fn arg_value<T: std::str::FromStr>(name: &str) -> Option<T> {
+    let args = std::env::args().collect::<Vec<_>>();
+    let idx = args.iter().position(|arg| arg == name)?;
+    args.get(idx + 1)?.parse().ok()
+}
+
 async fn game() {
     let mat = shaders::glow_material();
     let outline_texture = load_texture("res/outline.png").await.unwrap();
@@ -160,32 +412,21 @@ async fn game() {
             .collect()
     };
 
-    let mut tokens = HashMap::new();
     let mut token_texture_map = HashMap::new();
     for (i, t) in TokenType::ALL_REGULAR.iter().enumerate() {
-        token_texture_map.insert(t.clone(), token_textures[i].clone());
+        token_texture_map.insert(*t, token_textures[i]);
+    }
+    for type_ in [TokenType::Bomb, TokenType::Ingredient] {
+        let texture = load_texture(type_.to_sprite_name()).await.unwrap();
+        texture.set_filter(FilterMode::Nearest);
+        token_texture_map.insert(type_, texture);
     }
 
     let mut effect_player = EffectPlayer::new().await;
     effect_player.audio_effect_volume = 0.1;
 
-    let grid_size = 10;
-    for i in 0..grid_size {
-        for j in 0..grid_size {
-            let mut modulo = TokenType::ALL_REGULAR.len();
-            if i % 3 == 0 {
-                modulo -= 1
-            };
-            let idx = (i + j) % modulo;
-            let type_ = TokenType::ALL_REGULAR[idx];
-            let tex = token_textures[idx].clone();
-            let position = Position::new(i as i32, j as i32);
-            let token = Token::new(type_, tex.clone());
-            tokens.insert(position, token);
-        }
-    }
-
     let bg_colour = Color::from_rgba(75, 106, 115, 255);
+    let cell_colour = Color::from_rgba(60, 88, 96, 255);
 
     let mut camera =
         Camera2D::from_display_rect(Rect::new(0.0, 0.0, screen_width(), screen_height()));
@@ -197,7 +438,52 @@ async fn game() {
     camera.zoom = prebaked_zoom;
     camera.target = prebaked_offset;
 
-    let mut game_state = GameState::new(tokens, grid_size, effect_player);
+    let mut game_state = match resume_game() {
+        Some(save) => {
+            println!(
+                "Resuming {} after {} moves",
+                save.level.name,
+                save.snapshot.mode.moves_made()
+            );
+            GameState::from_save(save, effect_player)
+        }
+        None => {
+            let Some(game_state) = start_game(effect_player) else {
+                println!("Couldn't generate a playable board");
+                return;
+            };
+            game_state
+        }
+    };
+    game_state.record_path = Some(match arg_value::<String>("--record") {
+        Some(path) => PathBuf::from(path),
+        None => Path::new(REPLAYS_DIR).join(format!("last.{}", REPLAY_EXTENSION)),
+    });
+    if let Some(hint_delay) = arg_value("--hint-delay") {
+        game_state.hint_delay = hint_delay;
+    }
+    if let Some(depth) = arg_value("--undo-depth") {
+        game_state.history.depth = depth;
+    }
+    game_state.history.in_competitive = has_flag("--undo-anywhere");
+    game_state.invalid_swap_costs_move = has_flag("--strict-swaps");
+    game_state.save_path = Some(PathBuf::from(SAVE_PATH));
+    if let Some(name) = arg_value::<String>("--bot") {
+        match Strategy::from_name(&name) {
+            Some(strategy) => {
+                println!("Bot playing with {:?}", strategy);
+                game_state.bot = Some(Bot::new(strategy, game_state.rng.seed()));
+            }
+            None => println!(
+                "Unknown bot {}, expected greedy, lookahead[:depth] or montecarlo[:playouts]",
+                name
+            ),
+        }
+    }
+    // Closing the window saves the game rather than losing it
+    prevent_quit();
+    // Touches are read as they are, not passed off as the mouse as well
+    simulate_mouse_with_touch(false);
 
     loop {
         clear_background(bg_colour);
@@ -242,52 +528,124 @@ async fn game() {
 
         set_camera(&camera);
 
-        let mouse_pos = {
-            let (mouse_x, mouse_y) = mouse_position();
-            let mouse = camera.screen_to_world(Vec2::new(mouse_x, mouse_y));
-            Position::from_world_vec2(mouse)
+        let pointer = Pointer::read();
+        let pointer_world = {
+            let world = camera.screen_to_world(pointer.position);
+            (world.x, world.y)
+        };
+        let mouse_pos = Position::from_world(pointer_world);
+        // The replay or bot does the playing while one is being watched
+        let watching = game_state.playback.is_some() || game_state.bot.is_some();
+        replay_controls(&mut game_state);
+        // Slowing down a game whose result counts would give a timed level more real seconds
+        let can_scale = watching || !game_state.mode.kind.is_competitive();
+        let dt = clock_controls(&mut game_state.clock, can_scale);
+        // Nothing moves on while paused, short of stepping a frame, and stepped frames take no
+        // input so moves can't be lined up a frame at a time
+        let running = !game_state.clock.is_paused() || dt > 0.0;
+        let playing = !watching && !game_state.clock.is_paused();
+        // Pressing picks a token up, and dragging it onto a neighbour swaps the two
+        let input = if !playing {
+            None
+        } else if pointer.pressed {
+            Some(PlayerInput::Press(mouse_pos.clone(), pointer_world))
+        } else if pointer.down && game_state.selection.drag.is_some() {
+            Some(PlayerInput::Drag(pointer_world))
+        } else {
+            None
         };
 
-        match game_state.phase {
-            Phase::TakingInput => phases::taking_input_phase(mouse_pos.clone(), &mut game_state),
-            Phase::MovedAndAnimating(ref moved_positions) => {
-                // Having to clone this list to make borrow checker happy (i.e. can't borrow the
-                // vec inside the phase variant from the game state and pass it in mutably).
-                // sad times
-                let moved_positions = moved_positions.clone();
-                phases::post_token_swap_phase(&moved_positions, &mut game_state)
-            }
-            Phase::GravityRefill => {
-                phases::gravity_refill_phase(&mut game_state, &token_texture_map)
+        let now = game_state.clock.now();
+        if running {
+            game_state.tick_clock(dt);
+            if !matches!(game_state.phase, Phase::TakingInput) {
+                game_state.reset_idle(now);
             }
-            Phase::CheckWholeGrid => {
-                phases::check_whole_grid_phase(&mut game_state, &mut cross_positions)
+
+            match game_state.phase {
+                Phase::TakingInput if playing && is_key_pressed(KeyCode::Z) => game_state.undo(),
+                Phase::TakingInput if playing && is_key_pressed(KeyCode::Y) => game_state.redo(),
+                // Testers can step back out of a finished game too
+                Phase::LevelWon(_) | Phase::LevelFailed
+                    if playing && is_key_pressed(KeyCode::Z) =>
+                {
+                    game_state.undo()
+                }
+                _ => phases::run_phase(input, now, &mut game_state, &mut cross_positions),
             }
-            Phase::Animating(ref next_phase) => {
-                let next_phase = next_phase.clone();
-                phases::animating_phase(&mut game_state, next_phase);
+            game_state.autosave(now);
+
+            if !pointer.down || !matches!(game_state.phase, Phase::TakingInput) {
+                game_state.selection.release();
             }
         }
-
-        game_state.effect_player.update();
-        game_state.effect_player.draw();
+        if is_quit_requested() {
+            game_state.save_on_exit();
+            break;
+        }
 
         // Draw
+        for cell in game_state.board.shape.cells() {
+            let (x, y) = cell.to_world();
+            let (x2, y2) = Position::new(cell.x + 1, cell.y + 1).to_world();
+            draw_rectangle(x, y, x2 - x, y2 - y, cell_colour);
+        }
+        // Ice and stone go under the tokens, chains over them
+        for (pos, blocker) in game_state.board.blockers.iter() {
+            if blocker.kind != BlockerKind::Chain {
+                draw_blocker(pos, blocker);
+            }
+        }
+        let hint = game_state.hint.clone();
+        let draw_context = DrawContext {
+            shader_material: mat,
+            outline_texture: &outline_texture,
+            now,
+        };
         for (pos, token) in &mut game_state.tokens {
-            token.update();
+            token.update(now);
+            let Some(piece) = game_state.board.get(pos) else {
+                continue;
+            };
+            let texture = token_texture_map[&piece.type_];
 
-            let is_selected_already = game_state.selected_token_pos.as_ref() == Some(pos);
+            let is_selected_already = game_state.selection.selected.as_ref() == Some(pos);
             let modifier = if is_selected_already {
                 Modifier::Selected
             } else if pos == &mouse_pos {
                 Modifier::Hover
+            } else if hint.as_ref().is_some_and(|hint| hint.involves(pos)) {
+                Modifier::Hint
             } else {
                 Modifier::None
             };
 
-            token.draw(pos, &modifier, mat.clone(), &outline_texture);
+            // A held token follows the pointer
+            let offset = game_state
+                .selection
+                .drag
+                .as_ref()
+                .filter(|held| &held.from == pos)
+                .map_or((0.0, 0.0), Drag::offset);
+            token.draw(
+                texture,
+                piece.special,
+                pos,
+                offset,
+                &modifier,
+                &draw_context,
+            );
         }
 
+        for (pos, blocker) in game_state.board.blockers.iter() {
+            if blocker.kind == BlockerKind::Chain {
+                draw_blocker(pos, blocker);
+            }
+        }
+        // Effects go over the whole board so explosions and breaking blockers can be seen
+        game_state.effect_player.update(now);
+        game_state.effect_player.draw();
+
         draw_text(
             &format!("Phase: {:?}", game_state.phase),
             10.0,
@@ -295,6 +653,51 @@ async fn game() {
             10.0,
             WHITE,
         );
+        let (_, hud_y) = Position::new(0, game_state.board.shape.height as i32).to_world();
+        let mut hud = vec![format!("Level: {}", game_state.level.name)];
+        if game_state.mode.shows_score() {
+            hud.push(format!("Score: {}", game_state.score.total));
+            hud.push(format!("Last move: {}", game_state.score.last_move));
+            hud.push(format!("Best chain: {}", game_state.score.best_chain));
+        }
+        hud.push(format!("Seed: {}", game_state.rng.seed()));
+        hud.extend(game_state.mode.hud_lines());
+        if let Some(ref playback) = game_state.playback {
+            hud.push(format!(
+                "Replay: move {}/{}",
+                playback.position(),
+                playback.len()
+            ));
+            hud.push("Space: pause  1/2/4: speed  Left/Right/Home: seek".to_owned());
+        } else if let Some(ref bot) = game_state.bot {
+            hud.push(format!("Bot: {:?}", bot.strategy));
+        } else if game_state.history.is_enabled(&game_state.mode) {
+            hud.push("Z: undo  Y: redo".to_owned());
+        }
+        if game_state.invalid_swap_costs_move && game_state.mode.moves_left().is_some() {
+            hud.push("Swaps that don't match cost a move".to_owned());
+        }
+        if game_state.clock.is_paused() {
+            hud.push("Paused  P: resume  .: step a frame".to_owned());
+        }
+        if game_state.clock.scale() != 1.0 {
+            hud.push(format!("Game speed: {}x", game_state.clock.scale()));
+        }
+        for (objective, done, target) in game_state
+            .objectives
+            .progress(&game_state.board, &game_state.score)
+        {
+            hud.push(format!("{}: {}/{}", objective.describe(), done, target));
+        }
+        for (i, line) in hud.iter().enumerate() {
+            draw_text(line, 0.0, hud_y + 12.0 * (i + 1) as f32, 12.0, WHITE);
+        }
+
+        match game_state.phase {
+            Phase::LevelWon(stars) => draw_results(&game_state, Outcome::Won, stars),
+            Phase::LevelFailed => draw_results(&game_state, Outcome::Failed, 0),
+            _ => {}
+        }
 
         for cross_pos in cross_positions.iter() {
             let (x, y) = cross_pos.to_world();
@@ -302,8 +705,8 @@ async fn game() {
         }
 
         if is_mouse_button_pressed(MouseButton::Right) {
-            if let Some(token) = game_state.tokens.get_mut(&mouse_pos) {
-                println!("Token: {:?}", token);
+            if let Some(piece) = game_state.board.get(&mouse_pos) {
+                println!("Piece: {:?}", piece);
             }
         }
 
@@ -0,0 +1,191 @@
+use crate::level::Level;
+use serde::{Deserialize, Serialize};
+
+// Used when move-limited play is asked for on a level that doesn't set its own limit
+pub const DEFAULT_MOVE_LIMIT: u32 = 20;
+pub const DEFAULT_TIME_LIMIT: f64 = 60.0;
+
+/// The rules a game is played under, deciding when it ends
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum GameMode {
+    // A fixed number of swaps
+    MoveLimited(u32),
+    // Seconds on the clock, which only runs while the board is still
+    Timed(f64),
+    // Plays forever
+    Endless,
+    // Plays until the objectives are met, there's no way to lose
+    Zen,
+}
+
+impl GameMode {
+    /// The mode a level is played in unless asked otherwise
+    pub fn for_level(level: &Level) -> Self {
+        match level.moves {
+            Some(moves) => Self::MoveLimited(moves),
+            None => Self::Endless,
+        }
+    }
+
+    /// Modes where the result counts, so there's no taking moves back
+    pub fn is_competitive(&self) -> bool {
+        matches!(self, Self::MoveLimited(_) | Self::Timed(_))
+    }
+
+    /// Mode from its name as given on the command line, using `level` for any limits
+    pub fn from_name(name: &str, level: &Level) -> Option<Self> {
+        match name {
+            "moves" => Some(Self::MoveLimited(level.moves.unwrap_or(DEFAULT_MOVE_LIMIT))),
+            "timed" => Some(Self::Timed(DEFAULT_TIME_LIMIT)),
+            "endless" => Some(Self::Endless),
+            "zen" => Some(Self::Zen),
+            _ => None,
+        }
+    }
+}
+
+/// How a game finished
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Outcome {
+    Won,
+    Failed,
+}
+
+/// A game mode along with how much of its allowance has been used
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Mode {
+    pub kind: GameMode,
+    moves_made: u32,
+    time_used: f64,
+}
+
+impl Mode {
+    pub fn new(kind: GameMode) -> Self {
+        Self {
+            kind,
+            moves_made: 0,
+            time_used: 0.0,
+        }
+    }
+
+    pub fn moves_made(&self) -> u32 {
+        self.moves_made
+    }
+
+    /// Seconds the clock has run for
+    pub fn time_used(&self) -> f64 {
+        self.time_used
+    }
+
+    /// Call when a swap is committed
+    pub fn record_move(&mut self) {
+        self.moves_made += 1;
+    }
+
+    /// Run the clock for `dt` seconds. Callers leave it stopped while the board is animating.
+    pub fn tick(&mut self, dt: f64) {
+        if let GameMode::Timed(limit) = self.kind {
+            self.time_used = (self.time_used + dt).min(limit);
+        }
+    }
+
+    pub fn moves_left(&self) -> Option<u32> {
+        match self.kind {
+            GameMode::MoveLimited(limit) => Some(limit.saturating_sub(self.moves_made)),
+            _ => None,
+        }
+    }
+
+    pub fn time_left(&self) -> Option<f64> {
+        match self.kind {
+            GameMode::Timed(limit) => Some(limit - self.time_used),
+            _ => None,
+        }
+    }
+
+    /// Out of moves or time
+    pub fn is_used_up(&self) -> bool {
+        self.moves_left() == Some(0) || self.time_left().is_some_and(|time| time <= 0.0)
+    }
+
+    /// Whether the game is over, given whether the level's objectives are all met. Running out
+    /// of moves or time only fails a level that has objectives, otherwise it just ends the game.
+    pub fn outcome(&self, objectives_complete: bool, has_objectives: bool) -> Option<Outcome> {
+        match self.kind {
+            GameMode::Endless => None,
+            GameMode::Zen => objectives_complete.then_some(Outcome::Won),
+            GameMode::MoveLimited(_) | GameMode::Timed(_) => {
+                if objectives_complete {
+                    Some(Outcome::Won)
+                } else if self.is_used_up() {
+                    Some(if has_objectives {
+                        Outcome::Failed
+                    } else {
+                        Outcome::Won
+                    })
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    /// Zen is about relaxing, not points
+    pub fn shows_score(&self) -> bool {
+        self.kind != GameMode::Zen
+    }
+
+    /// Mode specific lines for the HUD
+    pub fn hud_lines(&self) -> Vec<String> {
+        match self.kind {
+            GameMode::MoveLimited(_) => {
+                vec![format!("Moves left: {}", self.moves_left().unwrap())]
+            }
+            GameMode::Timed(_) => vec![format!("Time left: {:.0}s", self.time_left().unwrap())],
+            GameMode::Endless => vec!["Endless".to_owned()],
+            GameMode::Zen => vec!["Zen".to_owned()],
+        }
+    }
+
+    /// Heading for the results screen
+    pub fn results_title(&self, outcome: Outcome) -> &'static str {
+        match (outcome, self.kind) {
+            (Outcome::Won, GameMode::Timed(_)) if self.is_used_up() => "Time's up!",
+            (Outcome::Won, GameMode::MoveLimited(_)) if self.is_used_up() => "Out of moves",
+            (Outcome::Won, _) => "Level complete!",
+            (Outcome::Failed, GameMode::Timed(_)) => "Out of time",
+            (Outcome::Failed, _) => "Out of moves",
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_end_conditions() {
+        let mut mode = Mode::new(GameMode::MoveLimited(2));
+        mode.record_move();
+        assert_eq!(mode.outcome(false, true), None);
+        mode.record_move();
+        assert_eq!(mode.outcome(false, true), Some(Outcome::Failed));
+        assert_eq!(mode.outcome(false, false), Some(Outcome::Won));
+        assert_eq!(mode.outcome(true, true), Some(Outcome::Won));
+
+        let mut mode = Mode::new(GameMode::Timed(60.0));
+        mode.tick(59.5);
+        assert_eq!(mode.outcome(false, false), None);
+        mode.tick(1.0);
+        assert_eq!(mode.time_left(), Some(0.0));
+        assert_eq!(mode.outcome(false, false), Some(Outcome::Won));
+        assert_eq!(mode.results_title(Outcome::Won), "Time's up!");
+
+        let mut mode = Mode::new(GameMode::Zen);
+        mode.tick(1000.0);
+        mode.record_move();
+        assert_eq!(mode.outcome(false, true), None);
+        assert_eq!(mode.outcome(true, true), Some(Outcome::Won));
+        assert_eq!(Mode::new(GameMode::Endless).outcome(true, true), None);
+    }
+}
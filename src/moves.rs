@@ -0,0 +1,155 @@
+use crate::bitboard::Bitboard;
+use crate::board::Board;
+use crate::core::{MatchKind, Position};
+use crate::piece::Piece;
+use crate::token_grid;
+use hashbrown::HashMap;
+
+/// A swap that the player is allowed to make, and what it would do
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ValidMove {
+    pub from: Position,
+    pub to: Position,
+    // Shape of the biggest match the swap makes, None when it only sets off specials
+    pub match_kind: Option<MatchKind>,
+    // Tokens removed by the swap itself, not counting any cascades after it
+    pub cleared: usize,
+}
+
+impl ValidMove {
+    pub fn involves(&self, pos: &Position) -> bool {
+        &self.from == pos || &self.to == pos
+    }
+}
+
+/// Every pair of neighbouring unlocked positions on the board, each pair once, in row order
+fn adjacent_pairs(board: &Board) -> Vec<(Position, Position)> {
+    let mut positions = board
+        .pieces
+        .keys()
+        .filter(|pos| !board.is_locked(pos))
+        .cloned()
+        .collect::<Vec<_>>();
+    positions.sort_by_key(|pos| (pos.y, pos.x));
+    let mut pairs = Vec::new();
+    for pos in positions {
+        for other in [
+            Position::new(pos.x + 1, pos.y),
+            Position::new(pos.x, pos.y + 1),
+        ] {
+            if board.contains(&other) && !board.is_locked(&other) {
+                pairs.push((pos.clone(), other));
+            }
+        }
+    }
+    pairs
+}
+
+/// Checks swaps for matches, on one bitboard of the whole board unless it's too wide for one
+enum SwapChecker<'a> {
+    Bits(Bitboard),
+    Pieces(&'a HashMap<Position, Piece>),
+}
+
+impl<'a> SwapChecker<'a> {
+    fn new(board: &'a Board) -> Self {
+        match Bitboard::from_pieces(&board.pieces) {
+            Some(bitboard) => Self::Bits(bitboard),
+            None => Self::Pieces(&board.pieces),
+        }
+    }
+
+    fn makes_match(&mut self, from: &Position, to: &Position) -> bool {
+        match self {
+            Self::Bits(bitboard) => bitboard.swap_makes_match(from, to),
+            Self::Pieces(pieces) => token_grid::swap_makes_match(pieces, from, to),
+        }
+    }
+}
+
+/// Every valid swap on the board as a pair of positions, in row order. Much cheaper than
+/// [`find_valid_moves`] as nothing is played out, for callers that only need to know which swaps
+/// can be made.
+pub fn valid_swaps(board: &Board) -> Vec<(Position, Position)> {
+    let mut checker = SwapChecker::new(board);
+    adjacent_pairs(board)
+        .into_iter()
+        .filter(|(from, to)| board.is_always_swappable(from, to) || checker.makes_match(from, to))
+        .collect()
+}
+
+/// Enumerate every valid swap on the board along with what it clears. The board itself is left
+/// untouched.
+pub fn find_valid_moves(board: &Board) -> Vec<ValidMove> {
+    valid_swaps(board)
+        .into_iter()
+        .map(|(from, to)| {
+            let mut after = board.clone();
+            let is_combo = after.is_special_combo(&from, &to);
+            after.swap(&from, &to);
+            let clear_result = if is_combo {
+                after.resolve_combo(&from, &to)
+            } else {
+                after.resolve_swap(&from, &to)
+            };
+            let match_kind = clear_result
+                .matches
+                .iter()
+                .max_by_key(|(group, _)| group.len())
+                .map(|(_, kind)| *kind);
+            ValidMove {
+                from,
+                to,
+                match_kind,
+                cleared: clear_result.removed.len(),
+            }
+        })
+        .collect()
+}
+
+/// Cheaper than [`find_valid_moves`] when all that matters is whether the board is playable
+pub fn has_valid_move(board: &Board) -> bool {
+    let mut checker = SwapChecker::new(board);
+    adjacent_pairs(board)
+        .iter()
+        .any(|(from, to)| board.is_always_swappable(from, to) || checker.makes_match(from, to))
+}
+
+/// The move to suggest to a stuck player: whichever clears the most, earliest in row order on a
+/// tie
+pub fn hint(board: &Board) -> Option<ValidMove> {
+    find_valid_moves(board)
+        .into_iter()
+        .rev()
+        .max_by_key(|valid_move| valid_move.cleared)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::board_from_rows;
+
+    #[test]
+    fn test_find_valid_moves() {
+        let board = board_from_rows(&["RGRR", "GBYB"]);
+        let before = board.pieces.clone();
+
+        let moves = find_valid_moves(&board);
+        assert_eq!(
+            moves,
+            vec![ValidMove {
+                from: Position::new(0, 0),
+                to: Position::new(1, 0),
+                match_kind: Some(MatchKind::Three),
+                cleared: 3,
+            }]
+        );
+        assert_eq!(
+            valid_swaps(&board),
+            vec![(Position::new(0, 0), Position::new(1, 0))]
+        );
+        assert!(has_valid_move(&board));
+        assert_eq!(hint(&board), moves.first().cloned());
+        assert_eq!(board.pieces, before);
+    }
+}
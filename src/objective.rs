@@ -0,0 +1,155 @@
+use crate::blocker::BlockerKind;
+use crate::board::{Board, ClearResult};
+use crate::piece::TokenType;
+use crate::score::Score;
+use hashbrown::HashMap;
+use serde::{Deserialize, Serialize};
+
+/// Something a level asks the player to do
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Objective {
+    // Reach this many points
+    Score(u64),
+    // Clear this many tokens of one colour
+    Collect(TokenType, u32),
+    // Break every ice blocker on the board
+    ClearIce,
+    // Bring this many ingredients down to the bottom of the board
+    Ingredients(u32),
+}
+
+impl Objective {
+    pub fn describe(&self) -> String {
+        match self {
+            Self::Score(target) => format!("Score {}", target),
+            Self::Collect(colour, target) => format!("Collect {} {:?}", target, colour),
+            Self::ClearIce => "Clear the ice".to_owned(),
+            Self::Ingredients(target) => format!("Bring down {} ingredients", target),
+        }
+    }
+}
+
+/// Running totals of everything the objectives care about over a game
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Objectives {
+    pub goals: Vec<Objective>,
+    collected: HashMap<TokenType, u32>,
+    ingredients: u32,
+}
+
+impl Objectives {
+    pub fn new(goals: Vec<Objective>) -> Self {
+        Self {
+            goals,
+            ..Default::default()
+        }
+    }
+
+    /// Total ingredients the level wants brought down
+    pub fn ingredients_needed(&self) -> u32 {
+        self.goals
+            .iter()
+            .map(|goal| match goal {
+                Objective::Ingredients(target) => *target,
+                _ => 0,
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn record(&mut self, clear_result: &ClearResult) {
+        for (_, piece) in clear_result.removed.iter() {
+            if piece.is_matchable() {
+                *self.collected.entry(piece.type_).or_insert(0) += 1;
+            }
+        }
+        self.ingredients += clear_result.collected.len() as u32;
+    }
+
+    /// How far along each goal is, as (goal, done so far, target)
+    pub fn progress(&self, board: &Board, score: &Score) -> Vec<(Objective, u64, u64)> {
+        self.goals
+            .iter()
+            .map(|goal| {
+                let (done, target) = match goal {
+                    Objective::Score(target) => (score.total, *target),
+                    Objective::Collect(colour, target) => (
+                        *self.collected.get(colour).unwrap_or(&0) as u64,
+                        *target as u64,
+                    ),
+                    Objective::ClearIce => {
+                        let ice_left = board
+                            .blockers
+                            .values()
+                            .filter(|blocker| blocker.kind == BlockerKind::Ice)
+                            .count();
+                        ((ice_left == 0) as u64, 1)
+                    }
+                    Objective::Ingredients(target) => (self.ingredients as u64, *target as u64),
+                };
+                (goal.clone(), done.min(target), target)
+            })
+            .collect()
+    }
+
+    /// Every goal met. A level with no goals is never complete.
+    pub fn is_complete(&self, board: &Board, score: &Score) -> bool {
+        !self.goals.is_empty()
+            && self
+                .progress(board, score)
+                .iter()
+                .all(|(_, done, target)| done >= target)
+    }
+}
+
+/// Stars for finishing a level: one for winning and another for each of `star_scores` reached
+pub fn stars(score: &Score, star_scores: &[u64]) -> u32 {
+    1 + star_scores
+        .iter()
+        .filter(|threshold| score.total >= **threshold)
+        .count() as u32
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::blocker::Blocker;
+    use crate::core::Position;
+    use crate::piece::Piece;
+    use crate::shape::BoardShape;
+
+    #[test]
+    fn test_objectives() {
+        let mut board = Board::new(HashMap::new(), BoardShape::square(3));
+        board
+            .blockers
+            .insert(Position::new(1, 1), Blocker::new(BlockerKind::Ice, 1));
+        let mut score = Score::new();
+        let mut objectives = Objectives::new(vec![
+            Objective::Collect(TokenType::Red, 2),
+            Objective::ClearIce,
+        ]);
+        assert!(!objectives.is_complete(&board, &score));
+
+        objectives.record(&ClearResult {
+            removed: vec![
+                (Position::new(0, 0), Piece::new(TokenType::Red)),
+                (Position::new(1, 0), Piece::new(TokenType::Red)),
+                (Position::new(2, 0), Piece::new(TokenType::Red)),
+            ],
+            ..Default::default()
+        });
+        assert_eq!(
+            objectives.progress(&board, &score)[0],
+            (Objective::Collect(TokenType::Red, 2), 2, 2)
+        );
+        assert!(!objectives.is_complete(&board, &score));
+
+        board.blockers.clear();
+        assert!(objectives.is_complete(&board, &score));
+
+        score.total = 2500;
+        assert_eq!(stars(&score, &[2000, 3000]), 2);
+        assert_eq!(stars(&score, &[]), 1);
+    }
+}
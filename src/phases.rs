@@ -1,211 +1,377 @@
-use crate::animated_item::AnimatedItem;
 use crate::effect_player::EffectKind;
-use crate::game_state::GameState;
-use crate::token::{Token, TokenType};
-use crate::{check_for_matches, game, is_valid_swap, swap_tokens, Position};
-use hashbrown::HashMap;
-use macroquad::input::{is_mouse_button_pressed, mouse_position, MouseButton};
-use macroquad::texture::Texture2D;
-use rand::random;
-use std::collections::vec_deque::VecDeque;
-use std::hash::Hash;
+use crate::game_state::{GameState, BOT_MOVE_DELAY, SHUFFLE_ANIMATION_TIME};
+use matchup::core::Position;
+use matchup::generator;
+use matchup::mode::Outcome;
+use matchup::moves;
+use matchup::objective;
+use matchup::replay::ReplayMove;
+use matchup::simulation::Simulation;
 use std::rc::Rc;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default)]
 pub enum Phase {
     // User Input is displayed and taken
+    #[default]
     TakingInput,
     // Tokens were moved and we need to calculate
     MovedAndAnimating(Vec<Position>),
+    // Two specials were swapped together and go off as a combo
+    SpecialCombo(Vec<Position>),
     // Tokens may be missing and we need to refill from above
     GravityRefill,
     // Grid has changed due to gravity, check everything
     CheckWholeGrid,
+    // Board has settled with no valid moves left, rearrange it
+    Reshuffle,
+    // Every objective met, showing the results with the stars earned
+    LevelWon(u32),
+    // Out of moves with objectives still to do
+    LevelFailed,
+    // A swap that makes no match, its tokens swinging toward each other and back before input
+    // is taken again
+    SwapDenied,
     // Generic animation phase, will move onto the next phase when all animations are complete
     Animating(Rc<Phase>),
 }
 
-impl Default for Phase {
-    fn default() -> Self {
-        Self::TakingInput
+/// What the player did this frame
+#[derive(Clone, Debug, PartialEq)]
+pub enum PlayerInput {
+    // Clicked or touched a grid position, at a world position
+    Press(Position, (f32, f32)),
+    // Moved the pointer to a world position while it was down
+    Drag((f32, f32)),
+}
+
+/// Run a frame of whichever phase the game is in, with whatever the player did this frame
+pub fn run_phase(
+    input: Option<PlayerInput>,
+    now: f64,
+    game_state: &mut GameState,
+    cross_positions: &mut Vec<Position>,
+) {
+    // Hand back to the player once the last move of a replay has played out
+    if matches!(
+        game_state.phase,
+        Phase::TakingInput | Phase::LevelWon(_) | Phase::LevelFailed
+    ) && game_state
+        .playback
+        .as_ref()
+        .is_some_and(|playback| playback.is_finished())
+    {
+        println!("Replay finished, over to you");
+        game_state.playback = None;
+    }
+
+    match game_state.phase {
+        Phase::TakingInput if game_state.playback.is_some() => playback_phase(game_state),
+        Phase::TakingInput if game_state.bot.is_some() => bot_phase(now, game_state),
+        Phase::TakingInput => taking_input_phase(input, now, game_state),
+        Phase::MovedAndAnimating(ref moved_positions) => {
+            // Having to clone this list to make borrow checker happy (i.e. can't borrow the
+            // vec inside the phase variant from the game state and pass it in mutably).
+            // sad times
+            let moved_positions = moved_positions.clone();
+            post_token_swap_phase(&moved_positions, game_state)
+        }
+        Phase::SpecialCombo(ref moved_positions) => {
+            let moved_positions = moved_positions.clone();
+            special_combo_phase(&moved_positions, game_state)
+        }
+        Phase::GravityRefill => gravity_refill_phase(game_state),
+        Phase::CheckWholeGrid => check_whole_grid_phase(game_state, cross_positions),
+        Phase::Reshuffle => reshuffle_phase(game_state),
+        Phase::SwapDenied => swap_denied_phase(game_state),
+        Phase::LevelWon(_) | Phase::LevelFailed => {
+            let pressed = matches!(input, Some(PlayerInput::Press(..)));
+            results_phase(pressed, game_state)
+        }
+        Phase::Animating(ref next_phase) => {
+            let next_phase = next_phase.clone();
+            animating_phase(game_state, next_phase);
+        }
     }
 }
 
 pub fn animating_phase(game_state: &mut GameState, next: Rc<Phase>) {
     // Transition to the next phase when where are no animating tokens
-    let no_active_animations = {
-        let mut no_active_animations = true;
-        for token in game_state.tokens.values() {
-            if token.is_animating() {
-                no_active_animations = false;
-                break;
-            }
-        }
-        no_active_animations
-    };
+    let no_active_animations = !game_state.tokens.values().any(|t| t.is_animating());
 
     if no_active_animations {
         game_state.phase = (*next).clone()
     }
 }
 
-pub fn taking_input_phase(mouse_pos: Position, game_state: &mut GameState) {
-    if is_mouse_button_pressed(MouseButton::Left) {
-        println!(
-            "Mouse left clicked at {:?} ({:?})",
-            mouse_pos,
-            mouse_position()
-        );
+pub fn taking_input_phase(input: Option<PlayerInput>, now: f64, game_state: &mut GameState) {
+    // The clock can run out while waiting on the player
+    if let Some(end) = end_phase(game_state) {
+        game_state.phase = end;
+        return;
+    }
+
+    if game_state.hint.is_none() && now - game_state.idle_since > game_state.hint_delay {
+        game_state.hint = moves::hint(&game_state.board);
+        println!("Hint: {:?}", game_state.hint);
+    }
 
-        if game_state.tokens.contains_key(&mouse_pos) {
-            if let Some(ref already_selected_pos) = game_state.selected_token_pos.take() {
-                // TODO: Handle swap
-                if is_valid_swap(&mut game_state.tokens, already_selected_pos, &mouse_pos) {
-                    println!("Swapping tokens");
-                    let prev_token_pos = already_selected_pos.clone();
-                    let new_token_pos = mouse_pos.clone();
-                    swap_tokens(
-                        &mut game_state.tokens,
-                        prev_token_pos.clone(),
-                        new_token_pos.clone(),
-                        true,
-                    );
-
-                    // Transition phase
-                    game_state.phase = Phase::Animating(Rc::new(Phase::MovedAndAnimating(vec![
-                        prev_token_pos,
-                        new_token_pos,
-                    ])));
+    match input {
+        Some(PlayerInput::Press(pressed_pos, at)) => {
+            println!("Pressed at {:?}", pressed_pos);
+            game_state.reset_idle(now);
+
+            if game_state.board.contains(&pressed_pos) {
+                if let Some((from, to)) = game_state.selection.press(pressed_pos, at) {
+                    try_swap(from, to, game_state);
                 }
-            } else {
-                game_state.selected_token_pos = Some(mouse_pos.clone());
             }
         }
+        Some(PlayerInput::Drag(at)) => {
+            if let Some((from, to)) = game_state.selection.drag_to(at) {
+                println!("Dragged from {:?} to {:?}", from, to);
+                game_state.reset_idle(now);
+                try_swap(from, to, game_state);
+            }
+        }
+        None => {}
     }
 }
 
-pub fn post_token_swap_phase(moved_positions: &Vec<Position>, game_state: &mut GameState) {
-    let matched_lines = check_for_matches(&mut game_state.tokens, moved_positions);
-    for line in matched_lines {
-        println!("Removing tokens in matched group {:?}", line);
-        for pos in line {
-            game_state.tokens.remove(&pos);
-            game_state
-                .effect_player
-                .spawn_effect(pos, EffectKind::Explosion);
-        }
+/// The player's swap of two positions, made if it's valid and otherwise turned down as long as
+/// they're neighbours on the board
+fn try_swap(pos_a: Position, pos_b: Position, game_state: &mut GameState) {
+    if game_state.board.is_valid_swap(&pos_a, &pos_b) {
+        commit_swap(pos_a, pos_b, game_state);
+    } else if pos_a.is_adjacent(&pos_b) && game_state.board.contains(&pos_b) {
+        let costs_move =
+            game_state.invalid_swap_costs_move && game_state.mode.moves_left().is_some();
+        deny_swap(pos_a, pos_b, costs_move, game_state);
+    }
+}
+
+/// Stands in for the player while a replay is watched, making each recorded swap once the game
+/// has been running as long as it had when the swap was made
+fn playback_phase(game_state: &mut GameState) {
+    if let Some(end) = end_phase(game_state) {
+        game_state.phase = end;
+        return;
+    }
+
+    let play_time = game_state.play_time;
+    let Some(replay_move) = game_state
+        .playback
+        .as_mut()
+        .and_then(|playback| playback.take_due(play_time))
+    else {
+        return;
+    };
+    // Catch the clock up in case the board took longer to settle than when it was recorded
+    let behind = replay_move.clock - game_state.mode.time_used();
+    game_state.mode.tick(behind.max(0.0));
+    if !replay_swap(&replay_move, game_state) {
+        game_state.playback = None;
     }
+}
+
+/// Make a swap from a replay, turning it down again if it was when it was recorded. Returns false,
+/// leaving the board alone, if it isn't a valid swap here, which means the replay doesn't belong
+/// to this board.
+pub fn replay_swap(replay_move: &ReplayMove, game_state: &mut GameState) -> bool {
+    if !replay_move.denied
+        && !game_state
+            .board
+            .is_valid_swap(&replay_move.from, &replay_move.to)
+    {
+        println!(
+            "Replay swaps {:?} and {:?} but that isn't valid here, stopping",
+            replay_move.from, replay_move.to
+        );
+        return false;
+    }
+    let (from, to) = (replay_move.from.clone(), replay_move.to.clone());
+    if replay_move.denied {
+        deny_swap(from, to, true, game_state);
+    } else {
+        commit_swap(from, to, game_state);
+    }
+    true
+}
+
+/// Lets the bot make a move once the board has been still for a moment
+fn bot_phase(now: f64, game_state: &mut GameState) {
+    if let Some(end) = end_phase(game_state) {
+        game_state.phase = end;
+        return;
+    }
+    if now - game_state.idle_since < BOT_MOVE_DELAY {
+        return;
+    }
+
+    let simulation = Simulation::from_snapshot(game_state.snapshot());
+    let Some((from, to)) = game_state
+        .bot
+        .as_mut()
+        .and_then(|bot| bot.choose(&simulation))
+    else {
+        return;
+    };
+    println!("Bot chose {:?} and {:?}", from, to);
+    game_state.reset_idle(now);
+    commit_swap(from, to, game_state);
+}
+
+/// Make a swap, whether the player chose it, a bot did or a replay is repeating it
+pub fn commit_swap(pos_a: Position, pos_b: Position, game_state: &mut GameState) {
+    println!("Swapping tokens");
+    game_state.record_history(&pos_a, &pos_b);
+    game_state.record_move(&pos_a, &pos_b, false);
+    game_state.swap_tokens(&pos_a, &pos_b);
+    game_state.score.start_move();
+    game_state.mode.record_move();
+
+    // Transition phase
+    let moved_positions = vec![pos_a, pos_b];
+    let next_phase = if game_state
+        .board
+        .is_special_combo(&moved_positions[0], &moved_positions[1])
+    {
+        Phase::SpecialCombo(moved_positions)
+    } else {
+        Phase::MovedAndAnimating(moved_positions)
+    };
+    game_state.phase = Phase::Animating(Rc::new(next_phase));
+}
+
+/// Turn down a swap that makes no match, swinging its tokens toward each other and back. If it
+/// `costs_move` it's used up a move all the same, and is recorded so it can be undone and
+/// replayed.
+pub fn deny_swap(pos_a: Position, pos_b: Position, costs_move: bool, game_state: &mut GameState) {
+    println!("Swap of {:?} and {:?} makes no match", pos_a, pos_b);
+    if costs_move {
+        game_state.record_history(&pos_a, &pos_b);
+        game_state.record_move(&pos_a, &pos_b, true);
+        game_state.mode.record_move();
+    }
+    game_state.animate_denied_swap(&pos_a, &pos_b);
+    let now = game_state.clock.now();
+    game_state
+        .effect_player
+        .spawn_effect(pos_a, EffectKind::Denied, now);
+    game_state.phase = Phase::SwapDenied;
+}
+
+/// Wait for the denied swap's tokens to get back before taking input again
+fn swap_denied_phase(game_state: &mut GameState) {
+    if !game_state.tokens.values().any(|t| t.is_animating()) {
+        game_state.phase = Phase::TakingInput;
+    }
+}
+
+pub fn post_token_swap_phase(moved_positions: &[Position], game_state: &mut GameState) {
+    let clear_result = game_state
+        .board
+        .resolve_swap(&moved_positions[0], &moved_positions[1]);
+    game_state.apply_clear(&clear_result);
 
     game_state.phase = Phase::GravityRefill;
 }
 
-pub fn gravity_refill_phase(
-    game_state: &mut GameState,
-    token_textures: &HashMap<TokenType, Texture2D>,
-) {
+pub fn special_combo_phase(moved_positions: &[Position], game_state: &mut GameState) {
+    let clear_result = game_state
+        .board
+        .resolve_combo(&moved_positions[0], &moved_positions[1]);
+    game_state.apply_clear(&clear_result);
+
+    game_state.phase = Phase::GravityRefill;
+}
+
+pub fn gravity_refill_phase(game_state: &mut GameState) {
     println!("Gravity Refill Phase");
-    //iterate from bottom to top, if there is a gap, move the token above it down to replace it
-    let mut gaps_by_x: HashMap<i32, usize> = HashMap::new();
-    /*
-    for y in (0..game_state.grid_size).rev() {
-        for x in 0..game_state.grid_size {
-            let pos = Position::new(x as i32, y as i32);
-            if !game_state.tokens.contains_key(&pos) {
-                //there is a gap here, move the token above it down
-                for y_above in (0..y).rev() {
-                    let pos_above = Position::new(x as i32, y_above as i32);
-                    if let Some(mut token) = game_state.tokens.remove(&pos_above) {
-                        let animation_time =
-                            (y - y_above) as f64 * crate::token::ANIMATION_TIME_PER_TILE;
-                        token.animate_move_to(pos_above.clone(), pos.clone(), animation_time);
-                        game_state.tokens.insert(pos, token);
-                        break;
-                    }
-                }
+    let moves = game_state.board.gravity_refill(&mut game_state.rng);
+    game_state.animate_moves(&moves);
+    game_state.phase = Phase::Animating(Rc::new(Phase::CheckWholeGrid));
+}
 
-                {
-                    let count = gaps_by_x.entry(x).or_insert(0);
-                    *count += 1;
-                }
-            }
-        }
+pub fn check_whole_grid_phase(game_state: &mut GameState, _cross_positions: &mut Vec<Position>) {
+    let clear_result = game_state.board.resolve_whole_grid();
+
+    if clear_result.is_empty() {
+        game_state.phase = settled_phase(game_state);
+    } else {
+        game_state.apply_clear(&clear_result);
+        game_state.phase = Phase::GravityRefill;
     }
-    */
-
-    for x in 0i32..(game_state.grid_size as i32) {
-        let mut known_gaps = VecDeque::new();
-        for y in (0i32..game_state.grid_size as i32).rev() {
-            let pos = Position::new(x as i32, y);
-            if !game_state.tokens.contains_key(&pos) {
-                known_gaps.push_back(pos);
-            } else {
-                //There is a token here, if we have any known gaps, move this token down to fill it
-                // and don't remember to treat this as a gap to fill now it's moved
-                if let Some(gap_pos) = known_gaps.pop_front() {
-                    //move this token down,
-                    println!("Found a gap at {:?} to fill with {:?}", gap_pos, pos);
-                    if let Some(mut token) = game_state.tokens.remove(&pos) {
-                        let dy = gap_pos.y - y;
-                        let animation_time = dy as f64 * crate::token::ANIMATION_TIME_PER_TILE;
-                        token.animate_move_to(pos.clone(), gap_pos.clone(), animation_time);
-                        game_state.tokens.insert(gap_pos.clone(), token);
-                    }
-                    known_gaps.push_back(pos);
-                }
-            }
+}
+
+pub fn reshuffle_phase(game_state: &mut GameState) {
+    game_state.selection.clear();
+    let moves = match generator::shuffle_board(&mut game_state.board, &mut game_state.rng) {
+        Some(moves) => {
+            println!("Shuffled the board");
+            moves
         }
-        // record unfilled gaps, we'll need to spawn that many tokens
-        gaps_by_x.insert(x, known_gaps.len());
-    }
-
-    // We we moved tokens down, we need to spawn new ones above
-    for (x, gap_count) in gaps_by_x.iter() {
-        let gap_count = *gap_count as i32;
-        if gap_count > 0 {
-            println!("Found {} gaps at x={}", gap_count, x);
-            let r = 1..=gap_count;
-            println!("r={:?}", r);
-            for i in r {
-                // We want to move from, say, 1 above to (gap_count - 1) below
-                // Let's say we have a gap len of 2, this means we need new tokens at y = 0 and y = 1
-                // so that's one:   y=-1 -> y=1,  y=-2 -> y=0
-                // i.e. for [i = 1, i = 2],    y = -i -> 2-i = -1 -> 2-1 = -1 -> 1
-                let pos_above = Position::new(*x as i32, -i);
-                let pos_below = Position::new(*x as i32, gap_count - i);
-                let new_token_type: TokenType = random();
-                let new_token_texture = token_textures.get(&new_token_type).unwrap().clone();
-                let mut new_token = Token::new(new_token_type, new_token_texture);
-                new_token.animate_move_to(
-                    pos_above.clone(),
-                    pos_below.clone(),
-                    crate::token::ANIMATION_TIME_PER_TILE * gap_count as f64,
+        None => {
+            println!("Couldn't shuffle the board, generating a new one");
+            let old_positions = game_state.board.pieces.keys().cloned().collect::<Vec<_>>();
+            let Some(moves) =
+                generator::regenerate_board(&mut game_state.board, &mut game_state.rng)
+            else {
+                // Nothing left to play, so the level is over
+                println!("Couldn't generate a playable board either, failing the level");
+                game_state.phase = Phase::LevelFailed;
+                return;
+            };
+            for pos in old_positions {
+                game_state.effect_player.spawn_effect(
+                    pos,
+                    EffectKind::Explosion,
+                    game_state.clock.now(),
                 );
-                println!(
-                    "Spawning new token, moving from {:?} to {:?}",
-                    pos_above, pos_below
-                );
-                game_state.tokens.insert(pos_below, new_token);
             }
+            game_state.tokens.clear();
+            moves
         }
-    }
-    game_state.phase = Phase::Animating(Rc::new(Phase::CheckWholeGrid));
+    };
+    game_state.animate_moves_over(&moves, SHUFFLE_ANIMATION_TIME);
+    game_state.phase = Phase::Animating(Rc::new(Phase::TakingInput));
 }
 
-pub fn check_whole_grid_phase(game_state: &mut GameState, _cross_positions: &mut Vec<Position>) {
-    let lines_with_match_kind = crate::token_grid::check_entire_grid(&game_state.tokens);
+/// The results phase if the game mode says the game is over
+fn end_phase(game_state: &GameState) -> Option<Phase> {
+    let objectives = &game_state.objectives;
+    let complete = objectives.is_complete(&game_state.board, &game_state.score);
+    match game_state
+        .mode
+        .outcome(complete, !objectives.goals.is_empty())?
+    {
+        Outcome::Won => {
+            let stars = objective::stars(&game_state.score, &game_state.level.star_scores);
+            println!("Level won with {} stars", stars);
+            Some(Phase::LevelWon(stars))
+        }
+        Outcome::Failed => {
+            println!("Level failed");
+            Some(Phase::LevelFailed)
+        }
+    }
+}
 
-    if lines_with_match_kind.is_empty() {
-        game_state.phase = Phase::TakingInput;
+/// Where to go once the board has stopped moving: the end of the game if it's over, otherwise
+/// back to the player
+fn settled_phase(game_state: &GameState) -> Phase {
+    if let Some(end) = end_phase(game_state) {
+        end
+    } else if moves::has_valid_move(&game_state.board) {
+        Phase::TakingInput
     } else {
-        for (line, _match_kind) in lines_with_match_kind {
-            println!("Removing tokens in matched group {:?}", line);
-            for pos in line {
-                game_state.tokens.remove(&pos);
-                game_state
-                    .effect_player
-                    .spawn_effect(pos, EffectKind::Explosion);
-            }
-        }
-        game_state.phase = Phase::GravityRefill;
+        println!("No valid moves left");
+        Phase::Reshuffle
+    }
+}
+
+/// Results screen, waits for a click to play the level again
+pub fn results_phase(clicked: bool, game_state: &mut GameState) {
+    if clicked && !game_state.restart() {
+        println!("Couldn't generate a playable board to play again on");
     }
 }
@@ -0,0 +1,112 @@
+use rand::distributions::{Distribution, Standard};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub enum TokenType {
+    Red,
+    Green,
+    Blue,
+    Yellow,
+    Purple,
+    Bomb,
+    // Can't be matched or blown up, only brought down to the bottom of the board
+    Ingredient,
+}
+
+impl TokenType {
+    pub const ALL_REGULAR: [TokenType; 5] = [
+        Self::Red,
+        Self::Green,
+        Self::Blue,
+        Self::Yellow,
+        Self::Purple,
+    ];
+    /// Regular tokens are the coloured ones that can form matches
+    pub fn is_regular(self) -> bool {
+        !matches!(self, Self::Bomb | Self::Ingredient)
+    }
+    pub fn to_sprite_name(self) -> &'static str {
+        match self {
+            Self::Red => "res/red_token.png",
+            Self::Green => "res/green_token.png",
+            Self::Blue => "res/blue_token.png",
+            Self::Yellow => "res/yellow_token.png",
+            Self::Purple => "res/purple_token.png",
+            Self::Bomb => "res/bomb.png",
+            Self::Ingredient => "res/ingredient.png",
+        }
+    }
+}
+
+impl Distribution<TokenType> for Standard {
+    fn sample<R: rand::Rng + ?Sized>(&self, rng: &mut R) -> TokenType {
+        match rng.gen_range(0..=4) {
+            0 => TokenType::Red,
+            1 => TokenType::Green,
+            2 => TokenType::Blue,
+            3 => TokenType::Yellow,
+            4 => TokenType::Purple,
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub enum LineDirection {
+    Horizontal,
+    Vertical,
+}
+
+/// Special tokens are created by big matches and clear more than their own cell when they go off
+#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub enum Special {
+    // Made from a match of four, clears its whole row or column. Keeps its colour
+    LineClear(LineDirection),
+    // Made from an L or T shape, clears the 3x3 area around it
+    Bomb,
+    // Made from a match of five, clears every token of the colour it is swapped with
+    ColourBomb,
+}
+
+/// The logical contents of a single board cell. This is everything the engine needs to know
+/// about a token, how it is drawn is left to the renderer.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Piece {
+    pub type_: TokenType,
+    pub special: Option<Special>,
+}
+
+impl Piece {
+    pub fn new(type_: TokenType) -> Self {
+        Self {
+            type_,
+            special: None,
+        }
+    }
+
+    /// Bombs and colour bombs have no colour of their own, line clearers keep the colour of the
+    /// match that made them
+    pub fn new_special(type_: TokenType, special: Special) -> Self {
+        let type_ = match special {
+            Special::LineClear(_) => type_,
+            Special::Bomb | Special::ColourBomb => TokenType::Bomb,
+        };
+        Self {
+            type_,
+            special: Some(special),
+        }
+    }
+
+    pub fn is_matchable(&self) -> bool {
+        self.type_.is_regular()
+    }
+
+    pub fn is_ingredient(&self) -> bool {
+        self.type_ == TokenType::Ingredient
+    }
+
+    /// Specials that go off when they are swapped, rather than needing to be matched
+    pub fn is_swap_activated(&self) -> bool {
+        matches!(self.special, Some(Special::Bomb | Special::ColourBomb))
+    }
+}
@@ -0,0 +1,34 @@
+use macroquad::input::{
+    is_mouse_button_down, is_mouse_button_pressed, is_mouse_button_released, mouse_position,
+    touches, MouseButton, TouchPhase,
+};
+use macroquad::math::Vec2;
+
+/// The mouse or a finger, whichever the player is using
+#[derive(Debug, Clone, Copy)]
+pub struct Pointer {
+    // Screen position in pixels
+    pub position: Vec2,
+    // Went down this frame
+    pub pressed: bool,
+    pub down: bool,
+}
+
+impl Pointer {
+    /// The first finger on the screen if there is one, otherwise the mouse
+    pub fn read() -> Self {
+        match touches().into_iter().min_by_key(|touch| touch.id) {
+            Some(touch) => Self {
+                position: touch.position,
+                pressed: touch.phase == TouchPhase::Started,
+                down: !matches!(touch.phase, TouchPhase::Ended | TouchPhase::Cancelled),
+            },
+            None => Self {
+                position: mouse_position().into(),
+                pressed: is_mouse_button_pressed(MouseButton::Left),
+                down: is_mouse_button_down(MouseButton::Left)
+                    && !is_mouse_button_released(MouseButton::Left),
+            },
+        }
+    }
+}
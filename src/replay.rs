@@ -0,0 +1,250 @@
+//! Replays are a game's level, mode and starting rng state plus every swap the player made.
+//! Since every random choice comes from the rng, playing the swaps back through the same phases
+//! reproduces the game exactly.
+use crate::core::Position;
+use crate::level::Level;
+use crate::mode::GameMode;
+use crate::rng::GameRng;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::Path;
+
+/// Bumped whenever the format changes in a way older replays can't be read
+pub const REPLAY_VERSION: u32 = 1;
+/// Where games are recorded to unless asked otherwise
+pub const REPLAYS_DIR: &str = "replays";
+pub const REPLAY_EXTENSION: &str = "replay";
+/// Speeds a replay can be watched at, set on the game clock
+pub const PLAYBACK_SPEEDS: [f64; 3] = [1.0, 2.0, 4.0];
+
+/// A swap along with when it was made
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReplayMove {
+    // Frames since the game started
+    pub frame: u64,
+    // Seconds since the game started
+    pub time: f64,
+    // Seconds on the mode's clock, which only runs while the board is still
+    pub clock: f64,
+    pub from: Position,
+    pub to: Position,
+    // A swap that made no match but still cost a move
+    #[serde(default)]
+    pub denied: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Replay {
+    pub version: u32,
+    pub level: Level,
+    pub mode: GameMode,
+    // The rng as it was before the starting board was built
+    pub seed: u64,
+    pub word_pos: u128,
+    pub moves: Vec<ReplayMove>,
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(std::io::Error),
+    Parse(ron::error::SpannedError),
+    Encode(ron::Error),
+    // Written by a different version of the game
+    Version(u32),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "couldn't access replay: {}", err),
+            Self::Parse(err) => write!(f, "malformed replay at {}", err),
+            Self::Encode(err) => write!(f, "couldn't write replay: {}", err),
+            Self::Version(version) => write!(
+                f,
+                "replay is version {} but only version {} can be played",
+                version, REPLAY_VERSION
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+impl Replay {
+    /// Start recording a game of `level` whose board is about to be built from `rng`
+    pub fn new(level: Level, mode: GameMode, rng: &GameRng) -> Self {
+        Self {
+            version: REPLAY_VERSION,
+            level,
+            mode,
+            seed: rng.seed(),
+            word_pos: rng.word_pos(),
+            moves: Vec::new(),
+        }
+    }
+
+    /// The rng to build the starting board from
+    pub fn rng(&self) -> GameRng {
+        GameRng::from_seed_and_word_pos(self.seed, self.word_pos)
+    }
+
+    pub fn record(&mut self, replay_move: ReplayMove) {
+        self.moves.push(replay_move);
+    }
+
+    /// Forget the last move, for when it is undone
+    pub fn unrecord(&mut self) -> Option<ReplayMove> {
+        self.moves.pop()
+    }
+
+    pub fn load(path: &Path) -> Result<Self, ReplayError> {
+        let text = std::fs::read_to_string(path).map_err(ReplayError::Io)?;
+        Self::from_ron(&text)
+    }
+
+    pub fn from_ron(text: &str) -> Result<Self, ReplayError> {
+        let replay: Replay = ron::from_str(text).map_err(ReplayError::Parse)?;
+        if replay.version != REPLAY_VERSION {
+            return Err(ReplayError::Version(replay.version));
+        }
+        Ok(replay)
+    }
+
+    /// Write the replay to `path` on one line, creating its directory if needed
+    pub fn save(&self, path: &Path) -> Result<(), ReplayError> {
+        let text = ron::to_string(self).map_err(ReplayError::Encode)?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(ReplayError::Io)?;
+        }
+        std::fs::write(path, text).map_err(ReplayError::Io)
+    }
+}
+
+/// Where a replay being watched is up to
+#[derive(Debug, Clone)]
+pub struct Playback {
+    pub replay: Replay,
+    // Index of the next move to make
+    next: usize,
+}
+
+impl Playback {
+    pub fn new(replay: Replay) -> Self {
+        Self { replay, next: 0 }
+    }
+
+    /// Number of moves made so far
+    pub fn position(&self) -> usize {
+        self.next
+    }
+
+    pub fn len(&self) -> usize {
+        self.replay.moves.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.replay.moves.is_empty()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.len()
+    }
+
+    pub fn next_move(&self) -> Option<&ReplayMove> {
+        self.replay.moves.get(self.next)
+    }
+
+    /// The next move if it was made by `time` seconds into the game, moving past it
+    pub fn take_due(&mut self, time: f64) -> Option<ReplayMove> {
+        let due = self.next_move().filter(|next| next.time <= time)?.clone();
+        self.next += 1;
+        Some(due)
+    }
+
+    /// Jump to having made `position` moves, returning where that actually landed. The caller
+    /// brings the game back to its start, then `make_move` makes each move again in turn,
+    /// returning false if it couldn't, which stops the seek just before that move.
+    pub fn seek(
+        &mut self,
+        position: usize,
+        mut make_move: impl FnMut(&ReplayMove) -> bool,
+    ) -> usize {
+        let position = position.min(self.len());
+        self.next = 0;
+        while self.next < position && make_move(&self.replay.moves[self.next]) {
+            self.next += 1;
+        }
+        self.next
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::moves::find_valid_moves;
+    use rand::RngCore;
+
+    #[test]
+    fn test_replay() {
+        let level = Level::classic();
+        let mut rng = GameRng::from_seed(3);
+        // Start partway through the stream to check that's recorded too
+        rng.next_u32();
+        let mut replay = Replay::new(level.clone(), GameMode::Endless, &rng);
+        let mut board = level.build_board(&mut rng).unwrap();
+        for i in 0..5 {
+            let valid_move = find_valid_moves(&board).remove(0);
+            board.play_move(&valid_move.from, &valid_move.to, &mut rng);
+            replay.record(ReplayMove {
+                frame: i * 60,
+                time: i as f64,
+                clock: 0.0,
+                from: valid_move.from,
+                to: valid_move.to,
+                denied: false,
+            });
+        }
+
+        let loaded = Replay::from_ron(&ron::to_string(&replay).unwrap()).unwrap();
+        assert_eq!(loaded, replay);
+
+        // Playing the moves back from the recorded rng state ends on the same board
+        let mut rng = loaded.rng();
+        let mut replayed = loaded.level.build_board(&mut rng).unwrap();
+        let mut playback = Playback::new(loaded);
+        assert!(playback.take_due(-1.0).is_none());
+        while let Some(replay_move) = playback.take_due(f64::MAX) {
+            replayed.play_move(&replay_move.from, &replay_move.to, &mut rng);
+        }
+        assert!(playback.is_finished());
+        assert_eq!(replayed.pieces, board.pieces);
+
+        // Seeking replays the moves onto a fresh board, stopping at one that can't be made
+        let seek = |playback: &mut Playback, position: usize| {
+            let mut rng = playback.replay.rng();
+            let mut board = playback.replay.level.build_board(&mut rng).unwrap();
+            playback.seek(position, |replay_move| {
+                let valid = board.is_valid_swap(&replay_move.from, &replay_move.to);
+                if valid {
+                    board.play_move(&replay_move.from, &replay_move.to, &mut rng);
+                }
+                valid
+            })
+        };
+        assert_eq!(seek(&mut playback, 2), 2);
+        assert_eq!(seek(&mut playback, 100), 5);
+        assert_eq!(playback.position(), 5);
+
+        let mut corrupted = Playback::new(replay.clone());
+        corrupted.replay.moves[3].to = Position::new(-1, -1);
+        assert_eq!(seek(&mut corrupted, 5), 3);
+        assert_eq!(corrupted.position(), 3);
+
+        let mut newer = replay;
+        newer.version = REPLAY_VERSION + 1;
+        let Err(ReplayError::Version(_)) = Replay::from_ron(&ron::to_string(&newer).unwrap())
+        else {
+            panic!("expected a version error");
+        };
+    }
+}
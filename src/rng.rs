@@ -0,0 +1,82 @@
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+/// Seedable random number generator used for every random choice the engine makes, so that a
+/// seed and a list of moves is enough to reproduce a whole game.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "RngState", into = "RngState")]
+pub struct GameRng {
+    seed: u64,
+    rng: ChaCha8Rng,
+}
+
+impl GameRng {
+    pub fn from_seed(seed: u64) -> Self {
+        Self {
+            seed,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+        }
+    }
+
+    /// Pick a fresh seed from the OS, for when reproducing the game doesn't matter
+    pub fn from_entropy() -> Self {
+        Self::from_seed(rand::random())
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// How far through the seed's stream we are, together with the seed this fully describes the
+    /// generator's state
+    pub fn word_pos(&self) -> u128 {
+        self.rng.get_word_pos()
+    }
+
+    pub fn from_seed_and_word_pos(seed: u64, word_pos: u128) -> Self {
+        let mut rng = Self::from_seed(seed);
+        rng.rng.set_word_pos(word_pos);
+        rng
+    }
+}
+
+/// What a [`GameRng`] is saved as, the generator itself being rebuilt from these
+#[derive(Serialize, Deserialize)]
+struct RngState {
+    seed: u64,
+    word_pos: u128,
+}
+
+impl From<RngState> for GameRng {
+    fn from(state: RngState) -> Self {
+        Self::from_seed_and_word_pos(state.seed, state.word_pos)
+    }
+}
+
+impl From<GameRng> for RngState {
+    fn from(rng: GameRng) -> Self {
+        Self {
+            seed: rng.seed,
+            word_pos: rng.word_pos(),
+        }
+    }
+}
+
+impl RngCore for GameRng {
+    fn next_u32(&mut self) -> u32 {
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.rng.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.rng.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.rng.try_fill_bytes(dest)
+    }
+}
@@ -0,0 +1,160 @@
+//! An in-progress game written to disk so it can be picked up again after closing the window.
+//! Saves are only taken while the board is still, so there's no half-resolved move to store.
+use crate::history::Snapshot;
+use crate::level::Level;
+use crate::replay::Replay;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::Path;
+
+/// Bumped whenever the format changes, with a migration added to [`SaveGame::from_ron`] for the
+/// old version if it can be brought up to date
+pub const SAVE_VERSION: u32 = 1;
+pub const SAVE_PATH: &str = "saves/game.ron";
+// Seconds between saves while the player is thinking, so the clock isn't lost in timed games
+pub const AUTOSAVE_INTERVAL: f64 = 10.0;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SaveGame {
+    pub version: u32,
+    pub level: Level,
+    // Board, score, objectives, mode and rng, the same as an undo step
+    pub snapshot: Snapshot,
+    // The game's recording so far, so it can still be replayed
+    pub recording: Replay,
+    pub play_time: f64,
+}
+
+/// Just enough of a save to tell which version wrote it
+#[derive(Deserialize)]
+struct SaveVersion {
+    version: u32,
+}
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(std::io::Error),
+    Parse(ron::error::SpannedError),
+    Encode(ron::Error),
+    // Written by a newer version of the game
+    TooNew(u32),
+    // Written by an older version that can't be migrated
+    TooOld(u32),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "couldn't access save: {}", err),
+            Self::Parse(err) => write!(f, "malformed save at {}", err),
+            Self::Encode(err) => write!(f, "couldn't write save: {}", err),
+            Self::TooNew(version) => write!(
+                f,
+                "save is version {}, newer than this game's {}",
+                version, SAVE_VERSION
+            ),
+            Self::TooOld(version) => write!(f, "save version {} is no longer supported", version),
+        }
+    }
+}
+
+impl std::error::Error for SaveError {}
+
+impl SaveGame {
+    pub fn new(level: Level, snapshot: Snapshot, recording: Replay, play_time: f64) -> Self {
+        Self {
+            version: SAVE_VERSION,
+            level,
+            snapshot,
+            recording,
+            play_time,
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, SaveError> {
+        let text = std::fs::read_to_string(path).map_err(SaveError::Io)?;
+        Self::from_ron(&text)
+    }
+
+    /// Parse a save, bringing it up to date if an older version wrote it
+    pub fn from_ron(text: &str) -> Result<Self, SaveError> {
+        let SaveVersion { version } = ron::from_str(text).map_err(SaveError::Parse)?;
+        match version {
+            SAVE_VERSION => ron::from_str(text).map_err(SaveError::Parse),
+            // Migrations from older versions go here as the format changes
+            version if version > SAVE_VERSION => Err(SaveError::TooNew(version)),
+            version => Err(SaveError::TooOld(version)),
+        }
+    }
+
+    /// Write the save to `path`, creating its directory if needed
+    pub fn save(&self, path: &Path) -> Result<(), SaveError> {
+        let text = ron::to_string(self).map_err(SaveError::Encode)?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(SaveError::Io)?;
+        }
+        std::fs::write(path, text).map_err(SaveError::Io)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::blocker::{Blocker, BlockerKind};
+    use crate::core::Position;
+    use crate::mode::{GameMode, Mode};
+    use crate::moves::find_valid_moves;
+    use crate::objective::Objectives;
+    use crate::rng::GameRng;
+    use crate::score::Score;
+
+    #[test]
+    fn test_save_round_trip() {
+        let level = Level::classic();
+        let mut rng = GameRng::from_seed(11);
+        let recording = Replay::new(level.clone(), GameMode::Timed(60.0), &rng);
+        let mut board = level.build_board(&mut rng).unwrap();
+        board
+            .blockers
+            .insert(Position::new(2, 2), Blocker::new(BlockerKind::Ice, 2));
+        let mut mode = Mode::new(GameMode::Timed(60.0));
+        mode.tick(12.5);
+        mode.record_move();
+        let mut score = Score::new();
+        score.total = 1234;
+        let snapshot = Snapshot {
+            board,
+            score,
+            objectives: Objectives::new(level.objectives.clone()),
+            mode,
+            rng,
+        };
+
+        let mut save = SaveGame::new(level, snapshot, recording, 20.0);
+        let loaded = SaveGame::from_ron(&ron::to_string(&save).unwrap()).unwrap();
+        let (mut before, mut after) = (save.snapshot.clone(), loaded.snapshot);
+        assert_eq!(after.board.pieces, before.board.pieces);
+        assert_eq!(after.board.blockers, before.board.blockers);
+        assert_eq!(after.score.total, 1234);
+        assert_eq!(after.mode, before.mode);
+
+        // The rng carries on from where it was, so the game plays on exactly the same
+        let valid_move = find_valid_moves(&before.board).remove(0);
+        before
+            .board
+            .play_move(&valid_move.from, &valid_move.to, &mut before.rng);
+        after
+            .board
+            .play_move(&valid_move.from, &valid_move.to, &mut after.rng);
+        assert_eq!(after.board.pieces, before.board.pieces);
+
+        save.version = SAVE_VERSION + 1;
+        let Err(SaveError::TooNew(_)) = SaveGame::from_ron(&ron::to_string(&save).unwrap()) else {
+            panic!("expected a newer save to be rejected");
+        };
+        save.version = 0;
+        let Err(SaveError::TooOld(0)) = SaveGame::from_ron(&ron::to_string(&save).unwrap()) else {
+            panic!("expected an old save to be rejected");
+        };
+    }
+}
@@ -0,0 +1,147 @@
+use crate::board::ClearResult;
+use crate::core::{MatchKind, Position};
+use crate::piece::Special;
+use hashbrown::HashSet;
+use serde::{Deserialize, Serialize};
+
+// Tokens removed by a special's blast rather than as part of a match
+pub const BLAST_POINTS_PER_TOKEN: u64 = 10;
+// Each hit a blocker takes
+pub const BLOCKER_HIT_POINTS: u64 = 20;
+
+pub fn points_per_token(kind: MatchKind) -> u64 {
+    match kind {
+        MatchKind::Three => 10,
+        MatchKind::Four => 15,
+        MatchKind::LShape | MatchKind::TShape => 20,
+        MatchKind::Five => 25,
+        MatchKind::SuperTShape => 30,
+    }
+}
+
+pub fn detonation_bonus(special: Special) -> u64 {
+    match special {
+        Special::LineClear(_) => 50,
+        Special::Bomb => 75,
+        Special::ColourBomb => 150,
+    }
+}
+
+/// Running score for a game. Every clear made during a move (the swap itself, then each cascade
+/// after gravity) is one link in the chain, and each link is worth more than the one before it.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Score {
+    pub total: u64,
+    pub last_move: u64,
+    pub best_chain: u32,
+    chain: u32,
+}
+
+impl Score {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Call when a swap is committed, before any of its clears are scored
+    pub fn start_move(&mut self) {
+        self.last_move = 0;
+        self.chain = 0;
+    }
+
+    /// Length of the chain so far in the current move
+    pub fn chain(&self) -> u32 {
+        self.chain
+    }
+
+    /// The multiplier the next clear will be scored at
+    pub fn multiplier(&self) -> u64 {
+        self.chain as u64 + 1
+    }
+
+    /// Score a clear as the next link in the chain, returning the points it earned
+    pub fn add_clear(&mut self, clear_result: &ClearResult) -> u64 {
+        if clear_result.is_empty() {
+            return 0;
+        }
+        let points = score_clear(clear_result) * self.multiplier();
+        self.chain += 1;
+        self.best_chain = self.best_chain.max(self.chain);
+        self.last_move += points;
+        self.total += points;
+        points
+    }
+}
+
+/// Points for a single clear, before any chain multiplier
+pub fn score_clear(clear_result: &ClearResult) -> u64 {
+    let mut points = 0;
+    let mut matched: HashSet<&Position> = HashSet::new();
+    for (group, kind) in clear_result.matches.iter() {
+        points += group.len() as u64 * points_per_token(*kind);
+        matched.extend(group.iter());
+    }
+    let blasted = clear_result
+        .removed
+        .iter()
+        .filter(|(pos, _)| !matched.contains(pos))
+        .count() as u64;
+    points += blasted * BLAST_POINTS_PER_TOKEN;
+    points += clear_result
+        .detonations
+        .iter()
+        .map(|(_, special)| detonation_bonus(*special))
+        .sum::<u64>();
+    points += clear_result.damaged.len() as u64 * BLOCKER_HIT_POINTS;
+    points
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::piece::{Piece, TokenType};
+
+    fn three_in_a_row() -> ClearResult {
+        let group = vec![
+            Position::new(0, 0),
+            Position::new(1, 0),
+            Position::new(2, 0),
+        ];
+        ClearResult {
+            removed: group
+                .iter()
+                .map(|pos| (pos.clone(), Piece::new(TokenType::Red)))
+                .collect(),
+            matches: vec![(group, MatchKind::Three)],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_cascade_multiplier() {
+        let mut score = Score::new();
+        score.start_move();
+        assert_eq!(score.add_clear(&three_in_a_row()), 30);
+        assert_eq!(score.add_clear(&three_in_a_row()), 60);
+        assert_eq!(score.add_clear(&ClearResult::default()), 0);
+        assert_eq!(score.last_move, 90);
+        assert_eq!(score.best_chain, 2);
+
+        score.start_move();
+        assert_eq!(score.add_clear(&three_in_a_row()), 30);
+        assert_eq!(score.total, 120);
+        assert_eq!(score.last_move, 30);
+        assert_eq!(score.best_chain, 2);
+    }
+
+    #[test]
+    fn test_blasts_and_detonations() {
+        let mut clear_result = three_in_a_row();
+        clear_result
+            .removed
+            .push((Position::new(5, 5), Piece::new(TokenType::Blue)));
+        clear_result
+            .detonations
+            .push((Position::new(1, 0), Special::Bomb));
+        assert_eq!(score_clear(&clear_result), 30 + 10 + 75);
+    }
+}
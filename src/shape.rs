@@ -0,0 +1,106 @@
+use crate::core::Position;
+use hashbrown::HashSet;
+use serde::{Deserialize, Serialize};
+
+/// The outline of a board: a `width` x `height` rectangle with some of its cells masked out.
+/// Masked cells never hold a piece, pieces fall straight past them and matches can't cross them.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct BoardShape {
+    pub width: usize,
+    pub height: usize,
+    blocked: HashSet<Position>,
+}
+
+impl BoardShape {
+    pub fn rectangle(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            blocked: HashSet::new(),
+        }
+    }
+
+    pub fn square(size: usize) -> Self {
+        Self::rectangle(size, size)
+    }
+
+    /// Shape drawn as rows of text, `.` for a hole and anything else for a cell, e.g.
+    ///
+    /// ```text
+    /// .##.##.
+    /// #######
+    /// .#####.
+    /// ..###..
+    /// ```
+    ///
+    /// Rows shorter than the longest one are treated as holes past their end.
+    pub fn from_rows(rows: &[&str]) -> Self {
+        let width = rows
+            .iter()
+            .map(|row| row.chars().count())
+            .max()
+            .unwrap_or(0);
+        let mut shape = Self::rectangle(width, rows.len());
+        for (y, row) in rows.iter().enumerate() {
+            let chars = row.chars().collect::<Vec<_>>();
+            for x in 0..width {
+                if chars.get(x).map_or(true, |c| *c == '.') {
+                    shape.block(Position::new(x as i32, y as i32));
+                }
+            }
+        }
+        shape
+    }
+
+    /// Mask out a cell
+    pub fn block(&mut self, pos: Position) {
+        self.blocked.insert(pos);
+    }
+
+    /// Whether `pos` is a cell a piece can sit in
+    pub fn is_cell(&self, pos: &Position) -> bool {
+        pos.x >= 0
+            && pos.y >= 0
+            && (pos.x as usize) < self.width
+            && (pos.y as usize) < self.height
+            && !self.blocked.contains(pos)
+    }
+
+    /// Every cell in the shape, in row order
+    pub fn cells(&self) -> Vec<Position> {
+        (0..self.height as i32)
+            .flat_map(|y| (0..self.width as i32).map(move |x| Position::new(x, y)))
+            .filter(|pos| self.is_cell(pos))
+            .collect()
+    }
+
+    /// The cells in column `x`, from the bottom up
+    pub fn column_bottom_up(&self, x: i32) -> Vec<Position> {
+        (0..self.height as i32)
+            .rev()
+            .map(|y| Position::new(x, y))
+            .filter(|pos| self.is_cell(pos))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_from_rows() {
+        let shape = BoardShape::from_rows(&["#.#", "###", "##"]);
+        assert_eq!((shape.width, shape.height), (3, 3));
+        assert!(shape.is_cell(&Position::new(0, 0)));
+        assert!(!shape.is_cell(&Position::new(1, 0)));
+        assert!(!shape.is_cell(&Position::new(2, 2)));
+        assert!(!shape.is_cell(&Position::new(3, 1)));
+        assert!(!shape.is_cell(&Position::new(0, -1)));
+        assert_eq!(shape.cells().len(), 7);
+        assert_eq!(
+            shape.column_bottom_up(1),
+            vec![Position::new(1, 2), Position::new(1, 1)]
+        );
+    }
+}
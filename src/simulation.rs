@@ -0,0 +1,128 @@
+//! A headless game, played a whole move at a time. It follows the same rules as the window's
+//! phase machine: clear, cascade, check for the end of the game, then reshuffle a dead board.
+use crate::core::Position;
+use crate::generator;
+use crate::history::Snapshot;
+use crate::level::Level;
+use crate::mode::{GameMode, Mode, Outcome};
+use crate::moves::{self, ValidMove};
+use crate::objective::{self, Objectives};
+use crate::rng::GameRng;
+use crate::score::Score;
+
+// Seconds each move takes off the clock in timed games, there being no player to wait on
+pub const SIMULATED_MOVE_TIME: f64 = 2.0;
+
+#[derive(Debug, Clone)]
+pub struct Simulation {
+    pub state: Snapshot,
+    // The board went dead and no playable one could be generated to replace it
+    stuck: bool,
+}
+
+impl Simulation {
+    /// Start `level` on a board built from `rng`, or None if no playable board could be built
+    pub fn new(level: &Level, mode: GameMode, mut rng: GameRng) -> Option<Self> {
+        let board = level.build_board(&mut rng)?;
+        Some(Self::from_snapshot(Snapshot {
+            board,
+            score: Score::new(),
+            objectives: Objectives::new(level.objectives.clone()),
+            mode: Mode::new(mode),
+            rng,
+        }))
+    }
+
+    /// Carry on from a game in progress
+    pub fn from_snapshot(state: Snapshot) -> Self {
+        Self {
+            state,
+            stuck: false,
+        }
+    }
+
+    pub fn valid_moves(&self) -> Vec<ValidMove> {
+        moves::find_valid_moves(&self.state.board)
+    }
+
+    /// Just the swaps that can be made, for when what they clear doesn't matter
+    pub fn valid_swaps(&self) -> Vec<(Position, Position)> {
+        moves::valid_swaps(&self.state.board)
+    }
+
+    /// Make a swap and play it out, returning the points it scored. The caller is expected to
+    /// only make valid swaps.
+    pub fn play(&mut self, pos_a: &Position, pos_b: &Position) -> u64 {
+        let state = &mut self.state;
+        state.score.start_move();
+        state.mode.record_move();
+        state.mode.tick(SIMULATED_MOVE_TIME);
+        for clear_result in state.board.play_move(pos_a, pos_b, &mut state.rng) {
+            state.score.add_clear(&clear_result);
+            state.objectives.record(&clear_result);
+        }
+        if self.outcome().is_none() && !moves::has_valid_move(&self.state.board) {
+            let state = &mut self.state;
+            if generator::shuffle_board(&mut state.board, &mut state.rng).is_none()
+                && generator::regenerate_board(&mut state.board, &mut state.rng).is_none()
+            {
+                // Nothing left to play, as when the window fails the level
+                self.stuck = true;
+            }
+        }
+        self.state.score.last_move
+    }
+
+    /// How the game ended, if it has
+    pub fn outcome(&self) -> Option<Outcome> {
+        if self.stuck {
+            return Some(Outcome::Failed);
+        }
+        let state = &self.state;
+        let complete = state.objectives.is_complete(&state.board, &state.score);
+        state
+            .mode
+            .outcome(complete, !state.objectives.goals.is_empty())
+    }
+
+    /// Stars earned on `level`, none unless the game was won
+    pub fn stars(&self, level: &Level) -> u32 {
+        match self.outcome() {
+            Some(Outcome::Won) => objective::stars(&self.state.score, &level.star_scores),
+            _ => 0,
+        }
+    }
+
+    /// How far along each objective is, from 0 to 1 for each one
+    pub fn objective_progress(&self) -> f64 {
+        let state = &self.state;
+        state
+            .objectives
+            .progress(&state.board, &state.score)
+            .iter()
+            .map(|(_, done, target)| *done as f64 / (*target).max(1) as f64)
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_simulation() {
+        let level = Level::classic();
+        let mut simulation =
+            Simulation::new(&level, GameMode::MoveLimited(3), GameRng::from_seed(5)).unwrap();
+        let mut total = 0;
+        while simulation.outcome().is_none() {
+            let valid_move = simulation.valid_moves().remove(0);
+            total += simulation.play(&valid_move.from, &valid_move.to);
+        }
+        assert_eq!(simulation.state.mode.moves_made(), 3);
+        assert_eq!(simulation.state.score.total, total);
+        // Classic has no objectives, so running out of moves just ends it
+        assert_eq!(simulation.outcome(), Some(Outcome::Won));
+        assert_eq!(simulation.stars(&level), 1);
+    }
+}
@@ -0,0 +1,338 @@
+//! Bots that pick moves for a [`Simulation`], whether that's a headless game or one in the window
+use crate::core::Position;
+use crate::level::Level;
+use crate::mode::{GameMode, Outcome};
+use crate::rng::GameRng;
+use crate::simulation::Simulation;
+use rand::seq::SliceRandom;
+
+pub const DEFAULT_LOOKAHEAD_DEPTH: u32 = 2;
+pub const DEFAULT_PLAYOUTS: u32 = 8;
+pub const DEFAULT_PLAYOUT_DEPTH: u32 = 3;
+// Games with no way to end are cut off after this many moves
+pub const DEFAULT_MAX_MOVES: u32 = 200;
+
+// Worth more than any score, so a win is always taken and a loss always avoided
+const WIN_VALUE: f64 = 1e9;
+// Points a fully met objective is worth when weighing up moves
+const OBJECTIVE_VALUE: f64 = 5000.0;
+
+/// How a bot chooses its moves
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Strategy {
+    // The swap that clears the most tokens by itself, ignoring cascades
+    Greedy,
+    // Play every line of moves this deep, knowing exactly what will fall in
+    Lookahead(u32),
+    // Try each move followed by random playouts and go with the best on average
+    MonteCarlo { playouts: u32, depth: u32 },
+}
+
+impl Strategy {
+    /// Strategy from its name on the command line, e.g. `greedy`, `lookahead:3` or
+    /// `montecarlo:16`
+    pub fn from_name(name: &str) -> Option<Self> {
+        let (name, arg) = match name.split_once(':') {
+            Some((name, arg)) => (name, Some(arg.parse().ok()?)),
+            None => (name, None),
+        };
+        match name {
+            "greedy" => Some(Self::Greedy),
+            "lookahead" => Some(Self::Lookahead(arg.unwrap_or(DEFAULT_LOOKAHEAD_DEPTH))),
+            "montecarlo" => Some(Self::MonteCarlo {
+                playouts: arg.unwrap_or(DEFAULT_PLAYOUTS),
+                depth: DEFAULT_PLAYOUT_DEPTH,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// A player that makes its own moves
+#[derive(Debug, Clone)]
+pub struct Bot {
+    pub strategy: Strategy,
+    // For the bot's own random choices, kept apart from the game's so watching a bot doesn't
+    // change what falls in
+    rng: GameRng,
+}
+
+impl Bot {
+    pub fn new(strategy: Strategy, seed: u64) -> Self {
+        Self {
+            strategy,
+            rng: GameRng::from_seed(seed),
+        }
+    }
+
+    /// The swap to make next, None if there are no valid moves
+    pub fn choose(&mut self, simulation: &Simulation) -> Option<(Position, Position)> {
+        let best = match self.strategy {
+            Strategy::Greedy => {
+                let valid_move = simulation
+                    .valid_moves()
+                    .into_iter()
+                    .rev()
+                    .max_by_key(|valid_move| valid_move.cleared)?;
+                (valid_move.from, valid_move.to)
+            }
+            Strategy::Lookahead(depth) => best_by(simulation.valid_swaps(), |from, to| {
+                let mut next = simulation.clone();
+                next.play(from, to);
+                lookahead(&next, depth.saturating_sub(1))
+            })?,
+            Strategy::MonteCarlo { playouts, depth } => {
+                best_by(simulation.valid_swaps(), |from, to| {
+                    let total: f64 = (0..playouts)
+                        .map(|_| {
+                            let mut next = simulation.clone();
+                            next.play(from, to);
+                            playout(&mut next, depth, &mut self.rng)
+                        })
+                        .sum();
+                    total / playouts.max(1) as f64
+                })?
+            }
+        };
+        Some(best)
+    }
+}
+
+/// The swap with the highest value, the first of them if there's a tie
+fn best_by(
+    swaps: Vec<(Position, Position)>,
+    mut value: impl FnMut(&Position, &Position) -> f64,
+) -> Option<(Position, Position)> {
+    let mut best = None;
+    let mut best_value = f64::NEG_INFINITY;
+    for (from, to) in swaps {
+        let value = value(&from, &to);
+        if best.is_none() || value > best_value {
+            best = Some((from, to));
+            best_value = value;
+        }
+    }
+    best
+}
+
+/// How good a position is for the player
+fn evaluate(simulation: &Simulation) -> f64 {
+    let score = simulation.state.score.total as f64;
+    match simulation.outcome() {
+        Some(Outcome::Won) => WIN_VALUE + score,
+        Some(Outcome::Failed) => -WIN_VALUE,
+        None => score + simulation.objective_progress() * OBJECTIVE_VALUE,
+    }
+}
+
+/// Value of the best line of `depth` more moves
+fn lookahead(simulation: &Simulation, depth: u32) -> f64 {
+    if depth == 0 || simulation.outcome().is_some() {
+        return evaluate(simulation);
+    }
+    let best = simulation
+        .valid_swaps()
+        .iter()
+        .map(|(from, to)| {
+            let mut next = simulation.clone();
+            next.play(from, to);
+            lookahead(&next, depth - 1)
+        })
+        .fold(f64::NEG_INFINITY, f64::max);
+    // Stuck with no moves at all
+    if best == f64::NEG_INFINITY {
+        evaluate(simulation)
+    } else {
+        best
+    }
+}
+
+/// Value after making up to `depth` random moves
+fn playout(simulation: &mut Simulation, depth: u32, rng: &mut GameRng) -> f64 {
+    for _ in 0..depth {
+        if simulation.outcome().is_some() {
+            break;
+        }
+        let Some((from, to)) = simulation.valid_swaps().choose(rng).cloned() else {
+            break;
+        };
+        simulation.play(&from, &to);
+    }
+    evaluate(simulation)
+}
+
+/// How a headless game went
+#[derive(Debug, Clone, PartialEq)]
+pub struct GameReport {
+    // None if the game was cut off before it ended
+    pub outcome: Option<Outcome>,
+    pub score: u64,
+    pub moves: u32,
+    pub stars: u32,
+    // How many clears each move set off, the swap's own included
+    pub cascades: Vec<u32>,
+}
+
+/// Have `bot` play a game of `level` from `seed` to the end, or until `max_moves` have been made.
+/// Returns None if no playable board could be generated to play on.
+pub fn play_game(
+    level: &Level,
+    mode: GameMode,
+    bot: &mut Bot,
+    seed: u64,
+    max_moves: u32,
+) -> Option<GameReport> {
+    let mut simulation = Simulation::new(level, mode, GameRng::from_seed(seed))?;
+    let mut cascades = Vec::new();
+    while simulation.outcome().is_none() && simulation.state.mode.moves_made() < max_moves {
+        let Some((from, to)) = bot.choose(&simulation) else {
+            break;
+        };
+        simulation.play(&from, &to);
+        cascades.push(simulation.state.score.chain());
+    }
+    Some(GameReport {
+        outcome: simulation.outcome(),
+        score: simulation.state.score.total,
+        moves: simulation.state.mode.moves_made(),
+        stars: simulation.stars(level),
+        cascades,
+    })
+}
+
+/// Totals over many headless games
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Summary {
+    pub games: u32,
+    // Games that never started, no playable board being generated for their seed
+    pub skipped: u32,
+    pub wins: u32,
+    pub moves: u64,
+    pub score: u64,
+    // Moves by how many clears they set off, e.g. `cascades[1]` is moves with a single clear
+    pub cascades: Vec<u64>,
+}
+
+impl Summary {
+    pub fn add(&mut self, report: &GameReport) {
+        self.games += 1;
+        self.wins += (report.outcome == Some(Outcome::Won)) as u32;
+        self.moves += report.moves as u64;
+        self.score += report.score;
+        for cascade in report.cascades.iter().map(|&cascade| cascade as usize) {
+            if self.cascades.len() <= cascade {
+                self.cascades.resize(cascade + 1, 0);
+            }
+            self.cascades[cascade] += 1;
+        }
+    }
+
+    pub fn skip(&mut self) {
+        self.skipped += 1;
+    }
+
+    /// Combine with another summary, e.g. one from another thread
+    pub fn merge(&mut self, other: &Summary) {
+        self.games += other.games;
+        self.skipped += other.skipped;
+        self.wins += other.wins;
+        self.moves += other.moves;
+        self.score += other.score;
+        if self.cascades.len() < other.cascades.len() {
+            self.cascades.resize(other.cascades.len(), 0);
+        }
+        for (total, count) in self.cascades.iter_mut().zip(other.cascades.iter()) {
+            *total += count;
+        }
+    }
+
+    pub fn win_rate(&self) -> f64 {
+        self.wins as f64 / self.games.max(1) as f64
+    }
+
+    pub fn average_moves(&self) -> f64 {
+        self.moves as f64 / self.games.max(1) as f64
+    }
+
+    pub fn average_score(&self) -> f64 {
+        self.score as f64 / self.games.max(1) as f64
+    }
+
+    /// Share of moves that set off exactly `length` clears, or at least that many if `and_over`
+    pub fn cascade_share(&self, length: usize, and_over: bool) -> f64 {
+        let count: u64 = if and_over {
+            self.cascades.iter().skip(length).sum()
+        } else {
+            self.cascades.get(length).copied().unwrap_or(0)
+        };
+        count as f64 / self.moves.max(1) as f64
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_strategies() {
+        assert_eq!(
+            Strategy::from_name("lookahead:3"),
+            Some(Strategy::Lookahead(3))
+        );
+        assert_eq!(Strategy::from_name("greedy"), Some(Strategy::Greedy));
+        assert_eq!(Strategy::from_name("lookahead:x"), None);
+
+        let level = Level::classic();
+        // Short games, lookahead gets expensive quickly
+        let mode = GameMode::MoveLimited(2);
+        let strategies = [
+            Strategy::Greedy,
+            Strategy::Lookahead(2),
+            Strategy::MonteCarlo {
+                playouts: 2,
+                depth: 2,
+            },
+        ];
+        for strategy in strategies {
+            let report = play_game(&level, mode, &mut Bot::new(strategy, 1), 9, 100).unwrap();
+            assert_eq!(report.moves, 2, "{:?}", strategy);
+            assert_eq!(report.outcome, Some(Outcome::Won));
+            // Same seeds, same game
+            assert_eq!(
+                play_game(&level, mode, &mut Bot::new(strategy, 1), 9, 100),
+                Some(report)
+            );
+        }
+
+        // Knowing what falls in next can only help over a single move
+        let greedy = play_game(
+            &level,
+            GameMode::MoveLimited(1),
+            &mut Bot::new(Strategy::Greedy, 1),
+            4,
+            1,
+        )
+        .unwrap();
+        let lookahead = play_game(
+            &level,
+            GameMode::MoveLimited(1),
+            &mut Bot::new(Strategy::Lookahead(1), 1),
+            4,
+            1,
+        )
+        .unwrap();
+        assert!(lookahead.score >= greedy.score);
+
+        let mut summary = Summary::default();
+        summary.add(&greedy);
+        let mut other = Summary::default();
+        other.add(&lookahead);
+        other.skip();
+        summary.merge(&other);
+        assert_eq!(summary.games, 2);
+        assert_eq!(summary.skipped, 1);
+        assert_eq!(summary.win_rate(), 1.0);
+        assert_eq!(summary.average_moves(), 1.0);
+        assert_eq!(summary.cascade_share(1, true), 1.0);
+    }
+}
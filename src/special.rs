@@ -0,0 +1,143 @@
+use crate::board::Board;
+use crate::core::{MatchKind, Position};
+use crate::piece::{LineDirection, Piece, Special, TokenType};
+use hashbrown::HashMap;
+
+/// The special token a match earns, if any
+pub fn special_for_match(kind: MatchKind, positions: &[Position]) -> Option<Special> {
+    match kind {
+        MatchKind::Three => None,
+        MatchKind::Four => {
+            let direction = if positions.iter().all(|pos| pos.y == positions[0].y) {
+                LineDirection::Horizontal
+            } else {
+                LineDirection::Vertical
+            };
+            Some(Special::LineClear(direction))
+        }
+        MatchKind::LShape | MatchKind::TShape => Some(Special::Bomb),
+        MatchKind::Five | MatchKind::SuperTShape => Some(Special::ColourBomb),
+    }
+}
+
+/// All the positions cleared by a special going off at `pos`. `target_colour` is the colour a
+/// colour bomb was swapped with, if it was set off some other way it picks the most common one.
+pub fn blast_area(
+    board: &Board,
+    pos: &Position,
+    special: Special,
+    target_colour: Option<TokenType>,
+) -> Vec<Position> {
+    match special {
+        Special::LineClear(LineDirection::Horizontal) => board
+            .pieces
+            .keys()
+            .filter(|other| other.y == pos.y)
+            .cloned()
+            .collect(),
+        Special::LineClear(LineDirection::Vertical) => board
+            .pieces
+            .keys()
+            .filter(|other| other.x == pos.x)
+            .cloned()
+            .collect(),
+        Special::Bomb => board
+            .pieces
+            .keys()
+            .filter(|other| (other.x - pos.x).abs() <= 1 && (other.y - pos.y).abs() <= 1)
+            .cloned()
+            .collect(),
+        Special::ColourBomb => {
+            let colour = target_colour.or_else(|| most_common_colour(board));
+            board
+                .pieces
+                .iter()
+                .filter(|(_, piece)| Some(piece.type_) == colour)
+                .map(|(other, _)| other.clone())
+                .chain(std::iter::once(pos.clone()))
+                .collect()
+        }
+    }
+}
+
+/// What happens when two specials are swapped into each other
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ComboEffect {
+    // Clear every position in the area, setting off any other specials caught in it
+    Clear(Vec<Position>),
+    // Turn every position into the given special and set them all off
+    Convert(Vec<Position>, Special),
+}
+
+/// The combined effect of swapping special `piece_a` with special `piece_b`, centred on `centre`:
+///  * line + line: clears the row and column through the centre
+///  * bomb + line: clears a cross three tokens wide
+///  * bomb + bomb: clears the 5x5 area around the centre
+///  * colour bomb + line: every token of the line clearer's colour becomes a line clearer
+///  * colour bomb + bomb: every token of the most common colour becomes a bomb
+///  * colour bomb + colour bomb: clears the whole board
+pub fn combo_effect(
+    board: &Board,
+    centre: &Position,
+    piece_a: &Piece,
+    piece_b: &Piece,
+) -> ComboEffect {
+    let in_area = |f: &dyn Fn(&Position) -> bool| -> Vec<Position> {
+        board.pieces.keys().filter(|pos| f(pos)).cloned().collect()
+    };
+    let of_colour = |colour: Option<TokenType>| -> Vec<Position> {
+        board
+            .pieces
+            .iter()
+            .filter(|(_, piece)| piece.is_matchable() && Some(piece.type_) == colour)
+            .map(|(pos, _)| pos.clone())
+            .collect()
+    };
+    let offset = |pos: &Position| ((pos.x - centre.x).abs(), (pos.y - centre.y).abs());
+
+    // Order the pair so there are half as many cases to match on
+    let (first, second) = if piece_a.special >= piece_b.special {
+        (piece_a, piece_b)
+    } else {
+        (piece_b, piece_a)
+    };
+    match (first.special.unwrap(), second.special.unwrap()) {
+        (Special::ColourBomb, Special::ColourBomb) => ComboEffect::Clear(in_area(&|_| true)),
+        (Special::ColourBomb, Special::LineClear(direction)) => {
+            ComboEffect::Convert(of_colour(Some(second.type_)), Special::LineClear(direction))
+        }
+        (Special::ColourBomb, Special::Bomb) => {
+            ComboEffect::Convert(of_colour(most_common_colour(board)), Special::Bomb)
+        }
+        (Special::Bomb, Special::Bomb) => ComboEffect::Clear(in_area(&|pos| {
+            let (dx, dy) = offset(pos);
+            dx <= 2 && dy <= 2
+        })),
+        (Special::Bomb, Special::LineClear(_)) => ComboEffect::Clear(in_area(&|pos| {
+            let (dx, dy) = offset(pos);
+            dx <= 1 || dy <= 1
+        })),
+        (Special::LineClear(_), Special::LineClear(_)) => {
+            ComboEffect::Clear(in_area(&|pos| pos.x == centre.x || pos.y == centre.y))
+        }
+        _ => unreachable!("pair was ordered so the larger special comes first"),
+    }
+}
+
+/// The regular colour with the most tokens on the board, ties going to the first in
+/// `TokenType::ALL_REGULAR`
+pub fn most_common_colour(board: &Board) -> Option<TokenType> {
+    let mut counts: HashMap<TokenType, usize> = HashMap::new();
+    for piece in board.pieces.values() {
+        if piece.is_matchable() {
+            *counts.entry(piece.type_).or_insert(0) += 1;
+        }
+    }
+    // Reversed so that ties go to the first colour, max_by_key picks the last of equal maximums
+    TokenType::ALL_REGULAR
+        .iter()
+        .rev()
+        .filter_map(|t| counts.get(t).map(|count| (*t, *count)))
+        .max_by_key(|(_, count)| *count)
+        .map(|(t, _)| t)
+}
@@ -0,0 +1,56 @@
+//! Fixtures shared by the tests
+use crate::board::Board;
+use crate::core::Position;
+use crate::piece::LineDirection::{Horizontal, Vertical};
+use crate::piece::{Piece, Special, TokenType};
+use crate::shape::BoardShape;
+use hashbrown::HashMap;
+
+/// Pieces laid out as rows of characters: R, G, B, Y and P for the colours, b for a bomb, c for a
+/// colour bomb, h and v for red horizontal and vertical line clearers, * for a plain bomb token
+/// and I for an ingredient. Anything else is an empty cell.
+pub(crate) fn pieces_from_rows(rows: &[&str]) -> HashMap<Position, Piece> {
+    let mut pieces = HashMap::new();
+    for (y, row) in rows.iter().enumerate() {
+        for (x, c) in row.chars().enumerate() {
+            let piece = match c {
+                'R' => Piece::new(TokenType::Red),
+                'G' => Piece::new(TokenType::Green),
+                'B' => Piece::new(TokenType::Blue),
+                'Y' => Piece::new(TokenType::Yellow),
+                'P' => Piece::new(TokenType::Purple),
+                'b' => Piece::new_special(TokenType::Bomb, Special::Bomb),
+                'c' => Piece::new_special(TokenType::Bomb, Special::ColourBomb),
+                'h' => Piece::new_special(TokenType::Red, Special::LineClear(Horizontal)),
+                'v' => Piece::new_special(TokenType::Red, Special::LineClear(Vertical)),
+                '*' => Piece::new(TokenType::Bomb),
+                'I' => Piece::new(TokenType::Ingredient),
+                _ => continue,
+            };
+            pieces.insert(Position::new(x as i32, y as i32), piece);
+        }
+    }
+    pieces
+}
+
+/// A rectangular board just big enough for `rows`, holding their [`pieces_from_rows`]
+pub(crate) fn board_from_rows(rows: &[&str]) -> Board {
+    let width = rows.iter().map(|row| row.len()).max().unwrap_or(0);
+    Board::new(
+        pieces_from_rows(rows),
+        BoardShape::rectangle(width, rows.len()),
+    )
+}
+
+/// The cells marked X in `rows`, in row order
+pub(crate) fn cells_from_rows(rows: &[&str]) -> Vec<Position> {
+    let mut cells = Vec::new();
+    for (y, row) in rows.iter().enumerate() {
+        for (x, c) in row.chars().enumerate() {
+            if c == 'X' {
+                cells.push(Position::new(x as i32, y as i32));
+            }
+        }
+    }
+    cells
+}
@@ -1,66 +1,107 @@
-use crate::core::{AnimationPosition, Position};
-use crate::token_grid;
-use hashbrown::HashMap;
-use macroquad::color::WHITE;
+use macroquad::color::{Color, WHITE};
 use macroquad::material::Material;
+use macroquad::math::vec2;
 use macroquad::math::vec4;
-use macroquad::prelude::{draw_texture, gl_use_default_material, gl_use_material, Texture2D};
-use rand::distributions::{Distribution, Standard};
+use macroquad::prelude::{
+    draw_rectangle, draw_texture, draw_texture_ex, gl_use_default_material, gl_use_material,
+    DrawTextureParams, Texture2D,
+};
+use matchup::core::Position;
+use matchup::piece::{LineDirection, Special};
+use matchup::tween::{Animation, Easing, Look, Property, Tween};
 
 pub const ANIMATION_TIME_PER_TILE: f64 = 0.2;
-
-#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
-pub enum TokenType {
-    Red,
-    Green,
-    Blue,
-    Yellow,
-    Purple,
-    Bomb,
-}
+// How far of the way to its neighbour a token leans when nudged
+pub const NUDGE_REACH: f32 = 0.4;
+// Seconds a newly made special takes to pop into place
+pub const APPEAR_ANIMATION_TIME: f64 = 0.25;
 
 #[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq)]
 pub enum Modifier {
     None,
     Hover,
     Selected,
+    // Part of the move suggested to an idle player
+    Hint,
+}
+
+/// Everything drawing a token needs that's the same for every token in a frame
+#[derive(Clone, Copy)]
+pub struct DrawContext<'a> {
+    pub shader_material: Material,
+    pub outline_texture: &'a Texture2D,
+    // Game time the frame is drawn at
+    pub now: f64,
 }
 
-#[derive(Debug, Clone)]
+/// The visual state of a token on the board. What the token *is* lives in the engine's
+/// [`matchup::piece::Piece`], this only tracks how it is currently being drawn.
+#[derive(Debug, Clone, Default)]
 pub struct Token {
-    pub type_: TokenType,
-    animation_position: Option<AnimationPosition>,
-    texture: Texture2D,
+    animation: Option<Animation>,
 }
 
 impl Token {
-    pub(crate) fn new(type_: TokenType, texture: Texture2D) -> Token {
-        Self {
-            type_,
-            animation_position: None,
-            texture,
+    pub fn new() -> Token {
+        Self::default()
+    }
+
+    /// A token that pops up out of nothing, like a special made by a match
+    pub fn appearing(now: f64) -> Token {
+        let mut token = Self::new();
+        token.animate(
+            Tween::Parallel(vec![
+                Tween::new(
+                    Property::Scale(0.0, 1.0),
+                    APPEAR_ANIMATION_TIME,
+                    Easing::Back,
+                ),
+                Tween::new(
+                    Property::Alpha(0.0, 1.0),
+                    APPEAR_ANIMATION_TIME,
+                    Easing::EaseOut,
+                ),
+            ]),
+            now,
+        );
+        token
+    }
+
+    pub fn update(&mut self, now: f64) {
+        if self
+            .animation
+            .as_ref()
+            .is_some_and(|animation| animation.is_done(now))
+        {
+            self.animation = None;
         }
     }
 
-    pub fn update(&mut self) {
-        if let Some(ref mut animation_position) = self.animation_position {
-            if animation_position.is_done() {
-                self.animation_position = None;
-            }
+    /// How the token at `grid_position` looks at game time `now`
+    pub fn look(&self, grid_position: &Position, now: f64) -> Look {
+        let mut look = Look::at(grid_position.to_world());
+        if let Some(ref animation) = self.animation {
+            animation.apply(now, &mut look);
         }
+        look
     }
 
     pub fn draw(
         &self,
+        texture: Texture2D,
+        special: Option<Special>,
         grid_position: &Position,
+        offset: (f32, f32),
         modifier: &Modifier,
-        shader_material: Material,
-        outline_texture: &Texture2D,
+        context: &DrawContext,
     ) {
-        let (x, y) = match self.animation_position {
-            Some(ref animation_position) => animation_position.get(),
-            None => grid_position.to_world(),
-        };
+        let DrawContext {
+            shader_material,
+            outline_texture,
+            now,
+        } = *context;
+        let look = self.look(grid_position, now);
+        let (x, y) = (look.position.0 + offset.0, look.position.1 + offset.1);
         match modifier {
             Modifier::None => gl_use_default_material(),
             Modifier::Hover => {
@@ -71,8 +112,59 @@ impl Token {
                 gl_use_default_material();
                 draw_texture(*outline_texture, x, y, WHITE);
             }
+            Modifier::Hint => {
+                gl_use_default_material();
+                draw_texture(*outline_texture, x, y, WHITE);
+                let pulse = 1.25 + 0.25 * (now as f32 * 6.0).sin();
+                shader_material.set_uniform("test_color", vec4(pulse, pulse, pulse, 1.));
+                gl_use_material(shader_material);
+            }
         }
-        draw_texture(self.texture, x, y, WHITE);
+        if special == Some(Special::ColourBomb) && *modifier == Modifier::None {
+            // Cycle through colours so it stands out from a regular bomb
+            let t = now as f32 * 3.0;
+            let colour = vec4(
+                1.0 + t.sin() * 0.5,
+                1.0 + (t + 2.1).sin() * 0.5,
+                1.0 + (t + 4.2).sin() * 0.5,
+                1.,
+            );
+            shader_material.set_uniform("test_color", colour);
+            gl_use_material(shader_material);
+        }
+        let (w, h) = (texture.width(), texture.height());
+        let [r, g, b, a] = look.colour;
+        draw_texture_ex(
+            texture,
+            x + w * (1.0 - look.scale) / 2.0,
+            y + h * (1.0 - look.scale) / 2.0,
+            Color::new(r, g, b, a * look.alpha),
+            DrawTextureParams {
+                dest_size: Some(vec2(w * look.scale, h * look.scale)),
+                rotation: look.rotation,
+                ..Default::default()
+            },
+        );
+
+        if let Some(Special::LineClear(direction)) = special {
+            gl_use_default_material();
+            let stripe_colour = Color::new(1.0, 1.0, 1.0, 0.7 * look.alpha);
+            for offset in [0.35, 0.6] {
+                match direction {
+                    LineDirection::Horizontal => {
+                        draw_rectangle(x, y + h * offset, w, h * 0.08, stripe_colour)
+                    }
+                    LineDirection::Vertical => {
+                        draw_rectangle(x + w * offset, y, w * 0.08, h, stripe_colour)
+                    }
+                }
+            }
+        }
+    }
+
+    /// Play `tween` from game time `now`, in place of any animation already playing
+    pub fn animate(&mut self, tween: Tween, now: f64) {
+        self.animation = Some(Animation::new(tween, now));
     }
 
     pub fn animate_move_to(
@@ -80,100 +172,53 @@ impl Token {
         from_position: Position,
         to_position: Position,
         animation_time: f64,
+        easing: Easing,
+        now: f64,
     ) {
-        self.animation_position = Some(AnimationPosition::new_start(
-            from_position,
-            to_position,
-            animation_time,
-        ));
-    }
-
-    pub fn is_animating(&self) -> bool {
-        self.animation_position.is_some()
-    }
-}
-
-impl TokenType {
-    pub const ALL_REGULAR: [TokenType; 5] = [
-        Self::Red,
-        Self::Green,
-        Self::Blue,
-        Self::Yellow,
-        Self::Purple,
-    ];
-    pub fn to_sprite_name(&self) -> &str {
-        match self {
-            Self::Red => "res/red_token.png",
-            Self::Green => "res/green_token.png",
-            Self::Blue => "res/blue_token.png",
-            Self::Yellow => "res/yellow_token.png",
-            Self::Purple => "res/purple_token.png",
-            Self::Bomb => "res/bomb.png",
-        }
-    }
-}
-
-impl Distribution<TokenType> for Standard {
-    fn sample<R: rand::Rng + ?Sized>(&self, rng: &mut R) -> TokenType {
-        match rng.gen_range(0..=4) {
-            0 => TokenType::Red,
-            1 => TokenType::Green,
-            2 => TokenType::Blue,
-            3 => TokenType::Yellow,
-            4 => TokenType::Purple,
-            _ => unreachable!(),
-        }
+        self.animate(
+            Tween::new(
+                Property::Position(from_position.to_world(), to_position.to_world()),
+                animation_time,
+                easing,
+            ),
+            now,
+        );
     }
-}
 
-pub fn is_valid_swap(
-    tokens: &mut HashMap<Position, Token>,
-    pos1: &Position,
-    pos2: &Position,
-) -> bool {
-    if pos1.is_adjacent(pos2) {
-        // Must trigger a match
-        println!(
-            "Swapping tokens to check for matches. Pos1: {:?}, Pos2: {:?}",
-            pos1, pos2
-        );
-        swap_tokens(tokens, pos1.clone(), pos2.clone(), false);
-        let matches_exist = {
-            let matches = token_grid::check_for_matches(tokens, &[pos1.clone(), pos2.clone()]);
-            !matches.is_empty()
-        };
-        println!(
-            "Swapping back tokens after checking. Pos1: {:?}, Pos2: {:?}",
-            pos1, pos2
+    /// Lean from `position` part of the way toward `toward` and swing back again
+    pub fn animate_nudge(
+        &mut self,
+        position: &Position,
+        toward: &Position,
+        animation_time: f64,
+        now: f64,
+    ) {
+        let (x, y) = position.to_world();
+        let (x2, y2) = toward.to_world();
+        let reach = (x + (x2 - x) * NUDGE_REACH, y + (y2 - y) * NUDGE_REACH);
+        self.animate(
+            Tween::Sequence(vec![
+                Tween::new(
+                    Property::Position((x, y), reach),
+                    animation_time / 2.0,
+                    Easing::EaseOut,
+                ),
+                Tween::new(
+                    Property::Position(reach, (x, y)),
+                    animation_time / 2.0,
+                    Easing::Back,
+                ),
+            ]),
+            now,
         );
-        swap_tokens(tokens, pos1.clone(), pos2.clone(), false);
+    }
 
-        matches_exist
-    } else {
-        false
+    /// Jump straight to the end of any animation
+    pub fn skip_animation(&mut self) {
+        self.animation = None;
     }
-}
 
-pub fn swap_tokens(
-    tokens: &mut HashMap<Position, Token>,
-    token_a_pos: Position,
-    token_b_pos: Position,
-    animate: bool,
-) {
-    let mut token_a = tokens.remove(&token_a_pos).unwrap();
-    let mut token_b = tokens.remove(&token_b_pos).unwrap();
-    if animate {
-        token_a.animate_move_to(
-            token_a_pos.clone(),
-            token_b_pos.clone(),
-            ANIMATION_TIME_PER_TILE,
-        );
-        token_b.animate_move_to(
-            token_b_pos.clone(),
-            token_a_pos.clone(),
-            ANIMATION_TIME_PER_TILE,
-        );
+    pub fn is_animating(&self) -> bool {
+        self.animation.is_some()
     }
-    tokens.insert(token_b_pos, token_a);
-    tokens.insert(token_a_pos, token_b);
 }
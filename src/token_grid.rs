@@ -1,267 +1,369 @@
-use crate::core::MatchKind;
-use crate::token::TokenType;
-use crate::{Position, Token};
+use crate::bitboard::Bitboard;
+use crate::core::{MatchKind, Position};
+use crate::piece::{Piece, TokenType};
 use hashbrown::{HashMap, HashSet};
-use std::ops::Range;
 
+// One step along a row and one down a column
+const DIRECTIONS: [(i32, i32); 2] = [(1, 0), (0, 1)];
+
+/// Matches made by tokens arriving at `dirty_positions`: every straight run of three or more
+/// through one of them, grouped with any runs crossing it into Ls and Ts. Each match is found
+/// once, however many of the dirty positions it goes through.
 pub fn check_for_matches(
-    tokens: &HashMap<Position, Token>,
+    tokens: &HashMap<Position, Piece>,
     dirty_positions: &[Position],
-) -> Vec<Vec<Position>> {
-    // Actually need to do this individually for each token in dirty_positions because they might have different types ... think about this
-
-    let mut matched_lines = Vec::new();
-    // >> Wrap below in a function that takes a type and an initial position
-    // Collect all contiguous tokens of this type
-    for (start_position) in dirty_positions {
-        let mut unchecked_positions = vec![start_position.clone()];
-        let mut checked_positions = HashSet::new();
-        println!("Checking for matches starting at {:?}", start_position);
-        let token_type = tokens.get(start_position).unwrap().type_.clone();
-        println!("The starting token is type {:?}", token_type);
+) -> Vec<(Vec<Position>, MatchKind)> {
+    match Bitboard::from_pieces(tokens) {
+        Some(bitboard) => bitboard.check_for_matches(dirty_positions),
+        None => hash_map_check_for_matches(tokens, dirty_positions),
+    }
+}
 
-        while let Some(position) = unchecked_positions.pop() {
-            checked_positions.insert(position.clone());
+/// Whether swapping the neighbouring tokens at `pos_a` and `pos_b` would make a match. `tokens`
+/// is left as it is, the swap is only made on a bitboard of the cells around it.
+pub fn swap_makes_match(
+    tokens: &HashMap<Position, Piece>,
+    pos_a: &Position,
+    pos_b: &Position,
+) -> bool {
+    Bitboard::around_swap(tokens, pos_a, pos_b).swap_makes_match(pos_a, pos_b)
+}
 
-            for neighbour in position.neighbours() {
-                if !checked_positions.contains(&neighbour) {
-                    if let Some(token) = tokens.get(&neighbour) {
-                        if token.type_ == token_type {
-                            unchecked_positions.push(neighbour.clone());
-                        }
-                    }
-                }
-            }
+/// [`swap_makes_match`] on the hash map directly, by looking up the swapped tokens in each
+/// other's place. The reference the bitboard is tested and benchmarked against.
+pub fn hash_map_swap_makes_match(
+    tokens: &HashMap<Position, Piece>,
+    pos_a: &Position,
+    pos_b: &Position,
+) -> bool {
+    let swapped = |pos: &Position| {
+        if pos == pos_a {
+            tokens.get(pos_b)
+        } else if pos == pos_b {
+            tokens.get(pos_a)
+        } else {
+            tokens.get(pos)
         }
+    };
+    !matches_through(swapped, &[pos_a.clone(), pos_b.clone()]).is_empty()
+}
 
-        // try and match lines
-        let ys = checked_positions.iter().map(|pos| pos.y);
-        let min_y = ys.clone().min().unwrap();
-        let max_y = ys.max().unwrap();
-
-        let xs = checked_positions.iter().map(|pos| pos.x);
-        let min_x = xs.clone().min().unwrap();
-        let max_x = xs.max().unwrap();
+/// [`check_for_matches`] on the hash map directly. Used for boards too wide for a bitboard, and
+/// as the reference the bitboard is tested and benchmarked against.
+pub fn hash_map_check_for_matches(
+    tokens: &HashMap<Position, Piece>,
+    dirty_positions: &[Position],
+) -> Vec<(Vec<Position>, MatchKind)> {
+    matches_through(|pos| tokens.get(pos), dirty_positions)
+}
 
-        for y in min_y..=max_y {
-            let mut line = Vec::new();
-            for x in min_x..=max_x {
-                let pos = Position { x, y };
-                if checked_positions.contains(&pos) {
-                    line.push(pos);
-                } else {
-                    if line.len() >= 3 {
-                        matched_lines.push(line.clone());
-                    }
-                    line.clear();
-                }
-            }
-            if line.len() >= 3 {
-                matched_lines.push(line.clone());
-            }
+/// [`check_for_matches`] with the pieces looked up through `piece_at`
+fn matches_through<'a>(
+    piece_at: impl Fn(&Position) -> Option<&'a Piece>,
+    dirty_positions: &[Position],
+) -> Vec<(Vec<Position>, MatchKind)> {
+    let mut lines: Vec<Vec<Position>> = Vec::new();
+    // Every cell of a run that's found is looked at in turn too, for runs crossing it
+    let mut unchecked_positions = dirty_positions.to_vec();
+    let mut checked_positions = HashSet::new();
+    let mut next = 0;
+    while let Some(position) = unchecked_positions.get(next).cloned() {
+        next += 1;
+        if !checked_positions.insert(position.clone()) {
+            continue;
         }
-
-        for x in min_x..=max_x {
-            let mut line = Vec::new();
-            for y in min_y..=max_y {
-                let pos = Position { x, y };
-                if checked_positions.contains(&pos) {
-                    line.push(pos);
-                } else {
-                    if line.len() >= 3 {
-                        matched_lines.push(line.clone());
-                    }
-                    line.clear();
-                }
-            }
-            if line.len() >= 3 {
-                matched_lines.push(line.clone());
+        let token_type = match piece_at(&position) {
+            Some(piece) if piece.is_matchable() => piece.type_,
+            _ => continue,
+        };
+        for direction in DIRECTIONS {
+            let run = run_through(&piece_at, &position, token_type, direction);
+            if run.len() >= 3 && !lines.contains(&run) {
+                unchecked_positions.extend(run.iter().cloned());
+                lines.push(run);
             }
         }
     }
-    println!("Matched lines: {:?}", matched_lines);
-    matched_lines
+    merge_intersecting_lines(&lines)
 }
 
-fn check_contiguous_area(
-    tokens: &HashMap<Position, Token>,
-    start_position: &Position,
-    token_type: &TokenType,
+/// The unbroken run of `token_type` tokens through `position` going in `direction`, from its
+/// first cell to its last
+fn run_through<'a>(
+    piece_at: &impl Fn(&Position) -> Option<&'a Piece>,
+    position: &Position,
+    token_type: TokenType,
+    (dx, dy): (i32, i32),
 ) -> Vec<Position> {
-    let mut unchecked_positions = vec![start_position.clone()];
-    let mut checked_positions = HashSet::new();
-
-    while let Some(position) = unchecked_positions.pop() {
-        checked_positions.insert(position.clone());
+    let is_same = |pos: &Position| piece_at(pos).is_some_and(|piece| piece.type_ == token_type);
+    let mut start = position.clone();
+    while is_same(&Position::new(start.x - dx, start.y - dy)) {
+        start = Position::new(start.x - dx, start.y - dy);
+    }
+    let mut run = Vec::new();
+    let mut pos = start;
+    while is_same(&pos) {
+        run.push(pos.clone());
+        pos = Position::new(pos.x + dx, pos.y + dy);
+    }
+    run
+}
 
-        for neighbour in position.neighbours() {
-            if !checked_positions.contains(&neighbour) {
-                if let Some(token) = tokens.get(&neighbour) {
-                    if token.type_ == *token_type {
-                        unchecked_positions.push(neighbour.clone());
-                    }
-                }
-            }
-        }
+/// Every match on the board
+pub fn check_entire_grid(tokens: &HashMap<Position, Piece>) -> Vec<(Vec<Position>, MatchKind)> {
+    match Bitboard::from_pieces(tokens) {
+        Some(bitboard) => bitboard.check_entire_grid(),
+        None => hash_map_check_entire_grid(tokens),
     }
-    checked_positions.into_iter().collect()
 }
 
-/// A struct that represents a direction in which we may want to check for continuous positions
-struct ByDirection {
-    // bounds are (] - i.e. start is inclusive, end is exclusive
-    bounds: (i32, i32),
-    get: fn(&Position) -> i32,
-    set: fn(&mut Position, i32),
+/// Returns the position shared by the two lines, if any
+fn lines_intersect(line1: &[Position], line2: &[Position]) -> Option<Position> {
+    line1.iter().find(|pos| line2.contains(pos)).cloned()
 }
 
-impl ByDirection {
-    fn to_range(&self) -> Range<i32> {
-        Range {
-            start: self.bounds.0,
-            end: self.bounds.1,
-        }
-    }
+fn is_line_end(line: &[Position], pos: &Position) -> bool {
+    line.first() == Some(pos) || line.last() == Some(pos)
 }
 
-fn find_lines(
-    matched_lines: &mut Vec<Vec<Position>>,
-    check_by_direction: &ByDirection,
-    step_by_direction: &ByDirection,
-    area: &[Position],
-) {
-    //We step in the "step_by_direction" direction, and check in the "check_by_direction" direction, so we can flip them in future
-    // One can imagine y is "step" and x is "check". we go thought y=0 ... y=10, and for each y we go through x=0 ... x=10
-    // only for each x do we check for continuous lines, breaking on gaps or when we reach the end of the range for x
-    let mut position = Position { x: 0, y: 0 };
-    for step_unit in step_by_direction.to_range() {
-        let mut line = Vec::new();
-        (step_by_direction.set)(&mut position, step_unit);
-        for check_unit in check_by_direction.to_range() {
-            (check_by_direction.set)(&mut position, check_unit);
-            if area.contains(&position) {
-                line.push(position.clone());
-            } else {
-                if line.len() >= 3 {
-                    matched_lines.push(line.clone());
+/// Group together lines that cross each other (so an L made of a horizontal and a vertical line
+/// is a single match) and work out what shape each group makes
+pub(crate) fn merge_intersecting_lines(lines: &[Vec<Position>]) -> Vec<(Vec<Position>, MatchKind)> {
+    // Label each line with the group it belongs to, flooding through intersecting lines
+    let mut group_of_line: Vec<Option<usize>> = vec![None; lines.len()];
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    for i in 0..lines.len() {
+        if group_of_line[i].is_some() {
+            continue;
+        }
+        let group_id = groups.len();
+        let mut group = Vec::new();
+        let mut unchecked_lines = vec![i];
+        group_of_line[i] = Some(group_id);
+        while let Some(line_idx) = unchecked_lines.pop() {
+            group.push(line_idx);
+            for other_idx in 0..lines.len() {
+                if group_of_line[other_idx].is_none()
+                    && lines_intersect(&lines[line_idx], &lines[other_idx]).is_some()
+                {
+                    group_of_line[other_idx] = Some(group_id);
+                    unchecked_lines.push(other_idx);
                 }
-                line.clear();
             }
         }
-        if line.len() >= 3 {
-            matched_lines.push(line.clone());
-        }
+        groups.push(group);
     }
-}
-
-// TODO: Consider passing around &[Position] instead of Vec<Position> to avoid copying
-fn lines_intersect(line1: Vec<Position>, line2: Vec<Position>) {}
 
-fn make_by_direction(
-    area: &[Position],
-    get: fn(&Position) -> i32,
-    set: fn(&mut Position, i32),
-) -> ByDirection {
-    let min = area.iter().map(get).min().unwrap();
-    let max = area.iter().map(get).max().unwrap();
-    ByDirection {
-        bounds: (min, max + 1),
-        get,
-        set,
-    }
-}
-
-fn check_contiguous_area_for_linearity(area: &[Position]) -> Vec<(Vec<Position>, MatchKind)> {
-    // check for lines in x and y
-    // check for intersection
-
-    /*
-    let (min_x, max_x, min_y, max_y) =
-        area.iter().fold((i32::MAX, 0, i32::MAX, 0),
-        |(min_x, max_x, min_y, max_y), pos| {
-        (min_x.min(pos.x), max_x.max(pos.x), min_y.min(pos.y), max_y.max(pos.y))
-        });
-
-    let by_direction_x = ByDirection {
-        bounds: (min_x, max_x),
-        get: Position::get_x,
-        set: Position::set_x,
-    };
-    let by_direction_y = ByDirection {
-        bounds: (min_y, max_y),
-        get: Position::get_y,
-        set: Position::set_y,
-    };
-     */
-    let by_direction_x = make_by_direction(area, Position::get_x, Position::set_x);
-    let by_direction_y = make_by_direction(area, Position::get_y, Position::set_y);
-
-    let mut matched_lines = Vec::new();
-    find_lines(&mut matched_lines, &by_direction_x, &by_direction_y, area);
-    find_lines(&mut matched_lines, &by_direction_y, &by_direction_x, area);
-
-    println!("Input: {:?}", area);
-    println!("Matched lines: {:?}", matched_lines);
-    println!("----");
-
-    // TODO: Check for intersections, we are lying here
-    matched_lines
+    groups
         .into_iter()
-        .map(|line| (line, MatchKind::Three))
+        .map(|group| {
+            let group_lines = group.iter().map(|i| &lines[*i]).collect::<Vec<_>>();
+            let mut positions: Vec<Position> = Vec::new();
+            for line in group_lines.iter() {
+                for pos in line.iter() {
+                    if !positions.contains(pos) {
+                        positions.push(pos.clone());
+                    }
+                }
+            }
+            (positions, classify_lines(&group_lines))
+        })
         .collect()
 }
 
-pub fn check_entire_grid(tokens: &HashMap<Position, Token>) -> Vec<(Vec<Position>, MatchKind)> {
-    let mut checked_positions = HashSet::new();
-    let mut contiguous_areas = Vec::new();
-
-    for (position, token) in tokens {
-        if !checked_positions.contains(position) {
-            checked_positions.insert(position.clone());
-            let contiguous_area = check_contiguous_area(tokens, position, &token.type_);
-            contiguous_areas.push(contiguous_area);
-        }
+/// Work out the MatchKind for a group of intersecting lines. Any crossing that happens away from
+/// the end of one of its lines makes a T, otherwise lines meeting at their corners make an L.
+/// A line of five or more with anything crossing it is a SuperT.
+fn classify_lines(lines: &[&Vec<Position>]) -> MatchKind {
+    let longest = lines.iter().map(|line| line.len()).max().unwrap_or(0);
+    if lines.len() == 1 {
+        return match longest {
+            0..=3 => MatchKind::Three,
+            4 => MatchKind::Four,
+            _ => MatchKind::Five,
+        };
+    }
+    if longest >= 5 {
+        return MatchKind::SuperTShape;
     }
 
-    let mut lines_with_match_kind = Vec::new();
-    for area in contiguous_areas {
-        if area.len() >= 3 {
-            // Don't bother checking unless it's an area of 3 or more, we can't get a three-in-a-row with less than three!
-            lines_with_match_kind.extend(check_contiguous_area_for_linearity(&area));
+    let mut is_t_shape = false;
+    for (i, line1) in lines.iter().enumerate() {
+        for line2 in lines.iter().skip(i + 1) {
+            if let Some(pos) = lines_intersect(line1, line2) {
+                if !is_line_end(line1, &pos) || !is_line_end(line2, &pos) {
+                    is_t_shape = true;
+                }
+            }
         }
     }
-    lines_with_match_kind
+    if is_t_shape {
+        MatchKind::TShape
+    } else {
+        MatchKind::LShape
+    }
+}
+
+/// [`check_entire_grid`] on the hash map directly, by checking for matches through every token.
+/// Used for boards too wide for a bitboard, and as the reference the bitboard is tested and
+/// benchmarked against.
+pub fn hash_map_check_entire_grid(
+    tokens: &HashMap<Position, Piece>,
+) -> Vec<(Vec<Position>, MatchKind)> {
+    let mut positions = tokens.keys().cloned().collect::<Vec<_>>();
+    // Row order, so matches come out in the same order whatever order the map is in
+    positions.sort_by_key(|pos| (pos.y, pos.x));
+    hash_map_check_for_matches(tokens, &positions)
 }
 
+#[cfg(test)]
 mod test {
     use super::*;
-    use crate::core::Position;
+    use crate::test_util::{cells_from_rows, pieces_from_rows};
 
     #[test]
-    fn test_areas() {
-        let input = vec![
-            Position { x: 6, y: 4 },
-            Position { x: 7, y: 4 },
-            Position { x: 8, y: 4 },
-            Position { x: 9, y: 4 },
+    fn test_match_kinds() {
+        let cases = vec![
+            (vec!["RRR"], vec![(3, MatchKind::Three)]),
+            (vec!["RRRR"], vec![(4, MatchKind::Four)]),
+            (vec!["RRRRR"], vec![(5, MatchKind::Five)]),
+            (vec!["RRR", "R..", "R.."], vec![(5, MatchKind::LShape)]),
+            (vec!["RRR", ".R.", ".R."], vec![(5, MatchKind::TShape)]),
+            (vec![".R.", "RRR", ".R."], vec![(5, MatchKind::TShape)]),
+            (
+                vec!["RRRRR", "..R..", "..R.."],
+                vec![(7, MatchKind::SuperTShape)],
+            ),
+            // Two parallel lines side by side are two separate matches
+            (
+                vec!["RRR", "RRR"],
+                vec![(3, MatchKind::Three), (3, MatchKind::Three)],
+            ),
         ];
+        for (rows, expected) in cases {
+            let mut result = hash_map_check_entire_grid(&pieces_from_rows(&rows))
+                .into_iter()
+                .map(|(positions, kind)| (positions.len(), kind))
+                .collect::<Vec<_>>();
+            result.sort_by_key(|(len, _)| *len);
+            assert_eq!(result, expected, "for {:?}", rows);
+        }
+    }
+
+    fn sorted_groups(groups: Vec<(Vec<Position>, MatchKind)>) -> Vec<(Vec<Position>, MatchKind)> {
+        let mut groups = groups
+            .into_iter()
+            .map(|(mut group, kind)| {
+                group.sort_by_key(|pos| (pos.y, pos.x));
+                (group, kind)
+            })
+            .collect::<Vec<_>>();
+        groups.sort_by_key(|(group, _)| (group[0].y, group[0].x));
+        groups
+    }
+
+    // Board, dirty positions, and each match expected as the cells it clears
+    type MatchCase = (
+        Vec<&'static str>,
+        Vec<(i32, i32)>,
+        Vec<(Vec<&'static str>, MatchKind)>,
+    );
 
-        let by_direction_x = make_by_direction(&input, Position::get_x, Position::set_x);
-        assert_eq!(by_direction_x.to_range(), 6..10);
-        let by_direction_y = make_by_direction(&input, Position::get_y, Position::set_y);
-        assert_eq!(by_direction_y.to_range(), 4..5);
-        let mut matched_lines = Vec::new();
-        find_lines(&mut matched_lines, &by_direction_y, &by_direction_x, &input);
-        assert_eq!(matched_lines, Vec::<Vec<Position>>::new());
-        find_lines(&mut matched_lines, &by_direction_x, &by_direction_y, &input);
-        assert_eq!(
-            matched_lines,
-            vec![vec![
-                Position { x: 6, y: 4 },
-                Position { x: 7, y: 4 },
-                Position { x: 8, y: 4 },
-                Position { x: 9, y: 4 }
-            ]]
-        );
+    #[test]
+    fn test_check_for_matches() {
+        use MatchKind::*;
+        let cases: Vec<MatchCase> = vec![
+            (vec!["RRR"], vec![(0, 0)], vec![(vec!["XXX"], Three)]),
+            (
+                vec!["R", "R", "R"],
+                vec![(0, 2)],
+                vec![(vec!["X", "X", "X"], Three)],
+            ),
+            (vec!["GRRRR"], vec![(2, 0)], vec![(vec![".XXXX"], Four)]),
+            (vec!["BBBBB"], vec![(4, 0)], vec![(vec!["XXXXX"], Five)]),
+            // Found from both swapped tokens, reported once
+            (
+                vec!["RRRR"],
+                vec![(1, 0), (2, 0)],
+                vec![(vec!["XXXX"], Four)],
+            ),
+            (
+                vec!["R..", "R..", "RRR"],
+                vec![(0, 0), (2, 2)],
+                vec![(vec!["X..", "X..", "XXX"], LShape)],
+            ),
+            // Swapped tokens of different colours each making their own match
+            (
+                vec![".GR", ".GR", ".GR"],
+                vec![(1, 1), (2, 1)],
+                vec![
+                    (vec![".X.", ".X.", ".X."], Three),
+                    (vec!["..X", "..X", "..X"], Three),
+                ],
+            ),
+            (
+                vec!["RRRGRRR"],
+                vec![(0, 0), (6, 0)],
+                vec![(vec!["XXX"], Three), (vec!["....XXX"], Three)],
+            ),
+            // Only runs through a moved token, not everything in the same coloured blob
+            (vec!["RRR", "RRR"], vec![(0, 0)], vec![(vec!["XXX"], Three)]),
+            (
+                vec!["RRRR", "RR..", "R..."],
+                vec![(3, 0)],
+                vec![(vec!["XXXX", "X...", "X..."], LShape)],
+            ),
+            (vec!["RRR", "GBG"], vec![(1, 1)], vec![]),
+            // Runs crossing the one through the moved token are part of the same match
+            (
+                vec!["R..", "R..", "RRR"],
+                vec![(2, 2)],
+                vec![(vec!["X..", "X..", "XXX"], LShape)],
+            ),
+            (
+                vec!["RRR", ".R.", ".R."],
+                vec![(1, 2)],
+                vec![(vec!["XXX", ".X.", ".X."], TShape)],
+            ),
+            (
+                vec![".R.", "RRR", ".R."],
+                vec![(1, 1)],
+                vec![(vec![".X.", "XXX", ".X."], TShape)],
+            ),
+            (
+                vec!["RRRRR", "..R..", "..R.."],
+                vec![(2, 2)],
+                vec![(vec!["XXXXX", "..X..", "..X.."], SuperTShape)],
+            ),
+            // Short of three, broken up, or nothing that can match
+            (vec!["RR"], vec![(0, 0)], vec![]),
+            (vec!["RR.R"], vec![(1, 0)], vec![]),
+            (vec!["RR*R"], vec![(1, 0)], vec![]),
+            (vec!["RRGR"], vec![(1, 0), (2, 0)], vec![]),
+            (vec!["***"], vec![(1, 0)], vec![]),
+            (vec!["III"], vec![(1, 0)], vec![]),
+            (vec!["RRR"], vec![(5, 5)], vec![]),
+            (vec!["RRR"], vec![], vec![]),
+        ];
+        for (rows, dirty, expected) in cases {
+            let tokens = pieces_from_rows(&rows);
+            let dirty = dirty
+                .iter()
+                .map(|&(x, y)| Position::new(x, y))
+                .collect::<Vec<_>>();
+            let expected = expected
+                .into_iter()
+                .map(|(cells, kind)| (cells_from_rows(&cells), kind))
+                .collect::<Vec<_>>();
+            for check in [check_for_matches, hash_map_check_for_matches] {
+                assert_eq!(
+                    sorted_groups(check(&tokens, &dirty)),
+                    sorted_groups(expected.clone()),
+                    "for {:?} from {:?}",
+                    rows,
+                    dirty
+                );
+            }
+        }
     }
 }
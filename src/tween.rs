@@ -0,0 +1,279 @@
+//! Tweens for how things are drawn: where they are, how big, turned how far, how see-through and
+//! what colour, each eased along a curve. Tweens run one after another in a sequence or together
+//! in a parallel group, and an [`Animation`] plays a tween from a point in game time.
+use std::f64::consts::PI;
+
+/// How a tween's progress is shaped over its duration
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Easing {
+    Linear,
+    // Starts slowly and speeds up
+    EaseIn,
+    // Starts quickly and slows down
+    EaseOut,
+    EaseInOut,
+    // Hits the end and bounces back off it a few times, like something landing
+    Bounce,
+    // Overshoots and springs back and forth before settling
+    Elastic,
+    // Overshoots the end a little and comes back to it
+    Back,
+}
+
+impl Easing {
+    /// Eased progress for linear progress `t`, both running from 0 to 1. Bounce, elastic and back
+    /// can go outside of that range on the way.
+    pub fn apply(self, t: f64) -> f64 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Self::Linear => t,
+            Self::EaseIn => t * t,
+            Self::EaseOut => 1.0 - (1.0 - t) * (1.0 - t),
+            Self::EaseInOut if t < 0.5 => 2.0 * t * t,
+            Self::EaseInOut => 1.0 - (2.0 - 2.0 * t).powi(2) / 2.0,
+            Self::Bounce => bounce(t),
+            Self::Elastic if t == 0.0 || t == 1.0 => t,
+            Self::Elastic => {
+                2f64.powf(-10.0 * t) * ((t * 10.0 - 0.75) * (2.0 * PI / 3.0)).sin() + 1.0
+            }
+            Self::Back => {
+                let overshoot = 1.70158;
+                1.0 + (overshoot + 1.0) * (t - 1.0).powi(3) + overshoot * (t - 1.0).powi(2)
+            }
+        }
+    }
+}
+
+fn bounce(t: f64) -> f64 {
+    const STRENGTH: f64 = 7.5625;
+    const SPAN: f64 = 2.75;
+    if t < 1.0 / SPAN {
+        STRENGTH * t * t
+    } else if t < 2.0 / SPAN {
+        let t = t - 1.5 / SPAN;
+        STRENGTH * t * t + 0.75
+    } else if t < 2.5 / SPAN {
+        let t = t - 2.25 / SPAN;
+        STRENGTH * t * t + 0.9375
+    } else {
+        let t = t - 2.625 / SPAN;
+        STRENGTH * t * t + 0.984375
+    }
+}
+
+fn lerp(from: f32, to: f32, t: f32) -> f32 {
+    from + (to - from) * t
+}
+
+/// Everything a tween can change about how something is drawn
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Look {
+    // World position of the top left corner
+    pub position: (f32, f32),
+    // 1 is full size, scaled about the centre
+    pub scale: f32,
+    // Radians clockwise, about the centre
+    pub rotation: f32,
+    // 1 is fully opaque
+    pub alpha: f32,
+    // Tint as red, green, blue and alpha, 1 each for none
+    pub colour: [f32; 4],
+}
+
+impl Look {
+    /// Drawn as is at `position`
+    pub fn at(position: (f32, f32)) -> Self {
+        Self {
+            position,
+            scale: 1.0,
+            rotation: 0.0,
+            alpha: 1.0,
+            colour: [1.0; 4],
+        }
+    }
+}
+
+/// One part of a [`Look`] going from a start value to an end value
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Property {
+    Position((f32, f32), (f32, f32)),
+    Scale(f32, f32),
+    Rotation(f32, f32),
+    Alpha(f32, f32),
+    Colour([f32; 4], [f32; 4]),
+}
+
+impl Property {
+    /// Set this property of `look` to `t` of the way from its start to its end
+    fn apply(&self, t: f32, look: &mut Look) {
+        match *self {
+            Self::Position(from, to) => {
+                look.position = (lerp(from.0, to.0, t), lerp(from.1, to.1, t));
+            }
+            Self::Scale(from, to) => look.scale = lerp(from, to, t),
+            Self::Rotation(from, to) => look.rotation = lerp(from, to, t),
+            Self::Alpha(from, to) => look.alpha = lerp(from, to, t),
+            Self::Colour(from, to) => {
+                for (i, channel) in look.colour.iter_mut().enumerate() {
+                    *channel = lerp(from[i], to[i], t);
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Tween {
+    // A single property over `duration` seconds
+    Property {
+        property: Property,
+        duration: f64,
+        easing: Easing,
+    },
+    // Seconds of nothing changing, to hold things up within a sequence
+    Wait(f64),
+    // Each tween once the one before it has finished
+    Sequence(Vec<Tween>),
+    // Every tween at once, finishing with the longest of them
+    Parallel(Vec<Tween>),
+}
+
+impl Tween {
+    pub fn new(property: Property, duration: f64, easing: Easing) -> Self {
+        Self::Property {
+            property,
+            duration,
+            easing,
+        }
+    }
+
+    /// Seconds from start to finish
+    pub fn duration(&self) -> f64 {
+        match self {
+            Self::Property { duration, .. } | Self::Wait(duration) => *duration,
+            Self::Sequence(tweens) => tweens.iter().map(Tween::duration).sum(),
+            Self::Parallel(tweens) => tweens.iter().map(Tween::duration).fold(0.0, f64::max),
+        }
+    }
+
+    /// Set `look` as it is `elapsed` seconds in. Tweens that haven't started yet leave it alone
+    /// and finished ones leave their end values, so later tweens in a sequence take over from
+    /// earlier ones.
+    pub fn apply(&self, elapsed: f64, look: &mut Look) {
+        if elapsed < 0.0 {
+            return;
+        }
+        match self {
+            Self::Property {
+                property,
+                duration,
+                easing,
+            } => {
+                let t = if *duration > 0.0 {
+                    elapsed / duration
+                } else {
+                    1.0
+                };
+                property.apply(easing.apply(t) as f32, look);
+            }
+            Self::Wait(_) => {}
+            Self::Sequence(tweens) => {
+                let mut start = 0.0;
+                for tween in tweens {
+                    tween.apply(elapsed - start, look);
+                    start += tween.duration();
+                }
+            }
+            Self::Parallel(tweens) => {
+                for tween in tweens {
+                    tween.apply(elapsed, look);
+                }
+            }
+        }
+    }
+}
+
+/// A tween playing from a moment in game time
+#[derive(Debug, Clone, PartialEq)]
+pub struct Animation {
+    pub tween: Tween,
+    start_time: f64,
+}
+
+impl Animation {
+    /// Start playing `tween` at game time `now`
+    pub fn new(tween: Tween, now: f64) -> Self {
+        Self {
+            tween,
+            start_time: now,
+        }
+    }
+
+    pub fn is_done(&self, now: f64) -> bool {
+        now - self.start_time > self.tween.duration()
+    }
+
+    /// Apply the animation to `look` as it stands at `now`
+    pub fn apply(&self, now: f64, look: &mut Look) {
+        self.tween.apply(now - self.start_time, look);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_tweens() {
+        let easings = [
+            Easing::Linear,
+            Easing::EaseIn,
+            Easing::EaseOut,
+            Easing::EaseInOut,
+            Easing::Bounce,
+            Easing::Elastic,
+            Easing::Back,
+        ];
+        for easing in easings {
+            assert!(easing.apply(0.0).abs() < 1e-9, "{:?}", easing);
+            assert!((easing.apply(1.0) - 1.0).abs() < 1e-9, "{:?}", easing);
+        }
+        let samples = (0..=100).map(|i| i as f64 / 100.0);
+        assert!(samples.clone().any(|t| Easing::Back.apply(t) > 1.0));
+        assert!(samples.clone().any(|t| Easing::Elastic.apply(t) > 1.0));
+        assert!(samples
+            .map(|t| Easing::Bounce.apply(t))
+            .all(|t| (0.0..=1.0 + 1e-9).contains(&t)));
+        assert_eq!(Easing::EaseInOut.apply(0.5), 0.5);
+
+        // Drop in, then fade out while shrinking
+        let tween = Tween::Sequence(vec![
+            Tween::new(
+                Property::Position((0.0, 0.0), (0.0, 64.0)),
+                1.0,
+                Easing::Linear,
+            ),
+            Tween::Wait(0.5),
+            Tween::Parallel(vec![
+                Tween::new(Property::Alpha(1.0, 0.0), 1.0, Easing::Linear),
+                Tween::new(Property::Scale(1.0, 0.5), 0.5, Easing::Linear),
+            ]),
+        ]);
+        assert_eq!(tween.duration(), 2.5);
+        let look_at = |elapsed| {
+            let mut look = Look::at((0.0, 0.0));
+            tween.apply(elapsed, &mut look);
+            look
+        };
+        assert_eq!(look_at(0.5).position, (0.0, 32.0));
+        assert_eq!(look_at(0.5).alpha, 1.0);
+        assert_eq!(look_at(1.25).position, (0.0, 64.0));
+        let fading = look_at(2.0);
+        assert_eq!((fading.alpha, fading.scale), (0.5, 0.5));
+        assert_eq!(look_at(10.0).alpha, 0.0);
+
+        let animation = Animation::new(tween, 3.0);
+        assert!(!animation.is_done(5.0));
+        assert!(animation.is_done(5.6));
+    }
+}